@@ -0,0 +1,238 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    Extension, RequestExt, Router,
+    extract::{MatchedPath, Path, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::{
+    error::AppError,
+    extractors::{ClientIp, Json},
+    rate_limit::RateLimitLayer,
+    recent_errors::{RecentErrors, RecordedError},
+    scraper_task::ScraperHandle,
+    search_settings::SearchSettings,
+    state::AppState,
+    v1,
+};
+
+/// The admin routes, which are only enabled if an admin token is configured.
+/// They are rate limited by IP, so that the token can't be brute forced, and the authorized
+/// requests are recorded in the audit log, while the rejected ones are only counted.
+pub fn admin_router(
+    app_state: AppState,
+    admin_token: Option<String>,
+    rate_limit: RateLimitLayer,
+    scraper: ScraperHandle,
+    recent_errors: RecentErrors,
+) -> Router<AppState> {
+    let Some(admin_token) = admin_token else {
+        return Router::new();
+    };
+
+    Router::new()
+        .route(
+            "/api/admin/search-settings",
+            get(get_search_settings).put(update_search_settings),
+        )
+        .route("/api/admin/scrape", post(trigger_scrape))
+        .route("/api/admin/scrape-progress", get(scrape_progress))
+        .route(
+            "/api/admin/search-index/rebuild",
+            post(rebuild_search_index),
+        )
+        .route("/api/admin/statistics/refresh", post(refresh_statistics))
+        .route("/api/admin/contracts/{id}/refetch", post(refetch_contract))
+        .route("/api/admin/errors", get(recent_errors_handler))
+        .route_layer(middleware::from_fn_with_state(
+            app_state,
+            audit_admin_action,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(admin_token),
+            require_admin_token,
+        ))
+        .route_layer(rate_limit)
+        .layer(Extension(scraper))
+        .layer(Extension(recent_errors))
+}
+
+/// Compares the hashes of the tokens in constant time, so that neither the token nor its length
+/// can be guessed by timing requests.
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    let expected = Sha256::digest(expected.as_bytes());
+    let actual = Sha256::digest(actual.as_bytes());
+
+    expected
+        .iter()
+        .zip(actual.iter())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+async fn require_admin_token(
+    State(admin_token): State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if tokens_match(&admin_token, token) => Ok(next.run(request).await),
+        _ => {
+            // not audited, so that the audit log can't be flooded without the token
+            metrics::counter!("admin_unauthorized_requests_total").increment(1);
+            Err(AppError::Unauthorized)
+        }
+    }
+}
+
+/// Records the request in the audit log once it is answered, with the status of the response.
+async fn audit_admin_action(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let action = match request.extensions().get::<MatchedPath>() {
+        Some(route) => format!("{} {}", request.method(), route.as_str()),
+        None => request.method().to_string(),
+    };
+    let path = request.uri().path().to_string();
+    let client_ip = request
+        .extract_parts::<ClientIp>()
+        .await
+        .ok()
+        .map(|ClientIp(ip)| ip.to_string());
+
+    let response = next.run(request).await;
+
+    // the action was already done, so it isn't undone if it can't be recorded
+    let status = response.status().as_u16();
+    if let Err(e) = state
+        .record_admin_action(&action, &path, status, client_ip.as_deref())
+        .await
+    {
+        error!("Failed to record admin action {action} ({path}): {e:?}");
+    }
+
+    response
+}
+
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn get_search_settings(
+    State(state): State<AppState>,
+) -> Result<Json<SearchSettings>, AppError> {
+    Ok(Json(state.get_search_settings().await?))
+}
+
+/// Replaces the search settings, where `version` must be the current version of the settings.
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn update_search_settings(
+    State(state): State<AppState>,
+    Json(settings): Json<SearchSettings>,
+) -> Result<Json<SearchSettings>, AppError> {
+    let settings = state.update_search_settings(settings).await?;
+
+    Ok(Json(settings))
+}
+
+/// Starts a scrape now, or right after the one that is running.
+#[tracing::instrument(skip(scraper))]
+#[axum::debug_handler]
+pub async fn trigger_scrape(
+    Extension(scraper): Extension<ScraperHandle>,
+) -> Result<StatusCode, AppError> {
+    scraper.trigger_scrape()?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeProgressResponse {
+    scraping: bool,
+    rebuilding_search_index: bool,
+    last_successful_scrape: Option<DateTime<Utc>>,
+    /// The ranges of pages that were completely saved, inclusive on both ends
+    saved_pages: Vec<(usize, usize)>,
+    /// The pages that were partially saved, with the number of contracts saved from each
+    pending_pages: BTreeMap<usize, usize>,
+}
+
+#[tracing::instrument(skip(state, scraper))]
+#[axum::debug_handler]
+pub async fn scrape_progress(
+    State(state): State<AppState>,
+    Extension(scraper): Extension<ScraperHandle>,
+) -> Result<Json<ScrapeProgressResponse>, AppError> {
+    let progress = scraper.store().scrape_progress();
+
+    Ok(Json(ScrapeProgressResponse {
+        scraping: scraper.is_scraping(),
+        rebuilding_search_index: scraper.is_rebuilding(),
+        last_successful_scrape: state.get_last_successful_scrape().await?,
+        saved_pages: progress.saved_pages().ranges().to_vec(),
+        pending_pages: progress
+            .pending_pages()
+            .iter()
+            .map(|(&page, ids)| (page, ids.len()))
+            .collect(),
+    }))
+}
+
+/// Rebuilds the search index from the contracts in the database, in the background.
+#[tracing::instrument(skip(scraper))]
+#[axum::debug_handler]
+pub async fn rebuild_search_index(
+    Extension(scraper): Extension<ScraperHandle>,
+) -> Result<StatusCode, AppError> {
+    scraper.rebuild_search_index()?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Refreshes the statistics now instead of waiting for the next periodic refresh.
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn refresh_statistics(
+    State(state): State<AppState>,
+) -> Result<Json<v1::Statistics>, AppError> {
+    state.reload_statistics().await?;
+
+    Ok(Json(state.get_statistics().statistics.into()))
+}
+
+/// Fetches the contract from Portal BASE again, replacing the saved one.
+#[tracing::instrument(skip(scraper))]
+#[axum::debug_handler]
+pub async fn refetch_contract(
+    Extension(scraper): Extension<ScraperHandle>,
+    Path(id): Path<u64>,
+) -> Result<Json<v1::Contract>, AppError> {
+    let contract = scraper.refetch_contract(id).await?;
+
+    Ok(Json(contract.into()))
+}
+
+/// The most recent errors logged by the backend, from the most recent.
+#[tracing::instrument(skip(recent_errors))]
+#[axum::debug_handler]
+pub async fn recent_errors_handler(
+    Extension(recent_errors): Extension<RecentErrors>,
+) -> Json<Vec<RecordedError>> {
+    Json(recent_errors.list())
+}
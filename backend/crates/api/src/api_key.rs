@@ -0,0 +1,214 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use common::{
+    api_keys::{ApiKeyTier, hash_api_key},
+    db::ContractDatabase,
+};
+use dashmap::DashMap;
+use tracing::{debug, error};
+
+use crate::{
+    error::{AppError, AppResult},
+    rate_limit::RateLimitLayer,
+    state::AppState,
+};
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// How long the keys are cached, which is also how long a revoked key keeps working.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+const ENTRIES_FOR_GC: usize = 1000;
+/// Invalid keys past this are looked up every time, so that random keys can't fill the cache,
+/// which is fine since those lookups are rate limited.
+const MAX_INVALID_KEYS: usize = 1000;
+const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The API key of a request, inserted in the request extensions by [api_key_layer].
+#[derive(Debug, Clone, Copy)]
+pub struct ApiKeyIdentity {
+    pub id: i64,
+    pub tier: ApiKeyTier,
+}
+
+struct CachedApiKey {
+    identity: ApiKeyIdentity,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    requests: u64,
+    rate_limited: u64,
+}
+
+/// Resolves the API keys sent by clients and accumulates their usage,
+/// so that the database isn't queried on every request.
+#[derive(Clone)]
+pub struct ApiKeys {
+    contract_database: ContractDatabase,
+    /// The resolved keys by the hash of the key
+    cache: Arc<DashMap<String, CachedApiKey>>,
+    /// When the keys that don't exist or are revoked expire, by the hash of the key
+    invalid_keys: Arc<DashMap<String, Instant>>,
+    usage: Arc<DashMap<i64, Usage>>,
+}
+
+impl ApiKeys {
+    pub fn new(contract_database: ContractDatabase) -> Self {
+        Self {
+            contract_database,
+            cache: Arc::new(DashMap::new()),
+            invalid_keys: Arc::new(DashMap::new()),
+            usage: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// The identity of the key if it is cached, which is None if it is cached as invalid.
+    fn cached(&self, hash: &str) -> Option<Option<ApiKeyIdentity>> {
+        let now = Instant::now();
+
+        if let Some(cached) = self.cache.get(hash)
+            && cached.expires_at > now
+        {
+            return Some(Some(cached.identity));
+        }
+
+        self.invalid_keys
+            .get(hash)
+            .filter(|expires_at| **expires_at > now)
+            .map(|_| None)
+    }
+
+    async fn lookup(&self, key: &str, hash: String) -> AppResult<Option<ApiKeyIdentity>> {
+        let identity = self
+            .contract_database
+            .find_api_key(key)
+            .await?
+            .map(|api_key| ApiKeyIdentity {
+                id: api_key.id,
+                tier: api_key.tier,
+            });
+
+        let now = Instant::now();
+        let expires_at = now + CACHE_TTL;
+
+        match identity {
+            Some(identity) => {
+                if self.cache.len() >= ENTRIES_FOR_GC {
+                    self.cache.retain(|_, cached| cached.expires_at > now);
+                }
+                self.invalid_keys.remove(&hash);
+                self.cache.insert(
+                    hash,
+                    CachedApiKey {
+                        identity,
+                        expires_at,
+                    },
+                );
+            }
+            None => {
+                if self.invalid_keys.len() >= MAX_INVALID_KEYS {
+                    self.invalid_keys.retain(|_, expires_at| *expires_at > now);
+                }
+                if self.invalid_keys.len() < MAX_INVALID_KEYS {
+                    self.invalid_keys.insert(hash, expires_at);
+                }
+            }
+        }
+
+        Ok(identity)
+    }
+
+    fn record_usage(&self, id: i64, rate_limited: bool) {
+        let mut usage = self.usage.entry(id).or_default();
+        usage.requests += 1;
+        usage.rate_limited += u64::from(rate_limited);
+    }
+
+    /// Saves the usage accumulated since the last flush.
+    async fn flush_usage(&self) {
+        let ids: Vec<i64> = self.usage.iter().map(|entry| *entry.key()).collect();
+
+        for id in ids {
+            let Some((_, usage)) = self.usage.remove(&id) else {
+                continue;
+            };
+
+            if let Err(e) = self
+                .contract_database
+                .record_api_key_usage(id, usage.requests, usage.rate_limited)
+                .await
+            {
+                error!("Failed to record usage of API key {id}: {e:?}");
+
+                // left to the next flush, along with the usage since it was removed
+                let mut pending = self.usage.entry(id).or_default();
+                pending.requests += usage.requests;
+                pending.rate_limited += usage.rate_limited;
+            }
+        }
+    }
+}
+
+pub async fn run_flush_usage_task(state: AppState) {
+    let mut interval = tokio::time::interval(USAGE_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.api_keys().flush_usage().await;
+    }
+}
+
+/// Identifies the clients that send an API key, so that they are rate limited by key
+/// instead of by IP. Requests without a key are anonymous, but an invalid key is rejected.
+/// Keys that aren't cached as valid are rate limited by IP with `lookup_limit`,
+/// before they are looked up.
+pub async fn api_key_layer(
+    State((state, lookup_limit)): State<(AppState, RateLimitLayer)>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(key) = request.headers().get(API_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+
+    let key = key
+        .to_str()
+        .map_err(|_| AppError::InvalidApiKey)?
+        .to_string();
+    let hash = hash_api_key(&key);
+
+    let identity = match state.api_keys().cached(&hash) {
+        Some(Some(identity)) => identity,
+        cached => {
+            if let Err(response) = lookup_limit.check_ip(&mut request).await {
+                return Ok(response);
+            }
+
+            let identity = match cached {
+                Some(identity) => identity,
+                None => state.api_keys().lookup(&key, hash).await?,
+            };
+            identity.ok_or(AppError::InvalidApiKey)?
+        }
+    };
+
+    debug!(api_key = identity.id, "Request with API key");
+    request.extensions_mut().insert(identity);
+
+    let response = next.run(request).await;
+    state.api_keys().record_usage(
+        identity.id,
+        response.status() == StatusCode::TOO_MANY_REQUESTS,
+    );
+
+    Ok(response)
+}
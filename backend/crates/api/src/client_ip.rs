@@ -0,0 +1,196 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::http::{HeaderMap, HeaderName, header};
+use ipnet::IpNet;
+
+const CF_CONNECTING_IP_HEADER: HeaderName = HeaderName::from_static("cf-connecting-ip");
+const X_FORWARDED_FOR_HEADER: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The proxies (ex: reverse proxy, frontend server) whose forwarding headers are trusted.
+/// The headers sent by any other peer are ignored, since they can be spoofed by the client.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    cidrs: Arc<[IpNet]>,
+    /// Whether the requests come through Cloudflare, which sets `CF-Connecting-IP`,
+    /// since otherwise the header is passed through by the proxies as sent by the client
+    trust_cf_connecting_ip: bool,
+}
+
+impl TrustedProxies {
+    pub fn new(cidrs: Vec<IpNet>, trust_cf_connecting_ip: bool) -> Self {
+        Self {
+            cidrs: cidrs.into(),
+            trust_cf_connecting_ip,
+        }
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.cidrs.iter().any(|cidr| cidr.contains(&ip))
+    }
+
+    /// Resolves the IP of the client that sent the request to `peer`, going back through
+    /// the forwarding headers while the hops are trusted proxies.
+    pub fn resolve(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.ip().to_canonical();
+        if !self.contains(&peer) {
+            return peer;
+        }
+
+        // set by cloudflare, which replaces the value sent by the client
+        let cf_connecting_ip = headers
+            .get(CF_CONNECTING_IP_HEADER)
+            .filter(|_| self.trust_cf_connecting_ip)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok());
+        if let Some(ip) = cf_connecting_ip {
+            return ip.to_canonical();
+        }
+
+        let chain = if headers.contains_key(header::FORWARDED) {
+            forwarded_chain(headers)
+        } else {
+            x_forwarded_for_chain(headers)
+        };
+
+        // every proxy appends the address of its peer, so the hops are read from the end
+        // until one isn't trusted, which is the first one that could have been spoofed
+        let mut client = peer;
+        for hop in chain.iter().rev() {
+            let Some(ip) = hop else {
+                break;
+            };
+
+            client = ip.to_canonical();
+            if !self.contains(&client) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+/// The addresses in the `X-Forwarded-For` headers (ex: "203.0.113.1, 10.0.0.1"),
+/// where None is an address that couldn't be parsed.
+fn x_forwarded_for_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    header_values(headers, &X_FORWARDED_FOR_HEADER)
+        .map(parse_node)
+        .collect()
+}
+
+/// The `for` addresses in the `Forwarded` headers as in RFC 7239
+/// (ex: `for=192.0.2.43;proto=https, for="[2001:db8:cafe::17]:4711"`),
+/// where None is an address that couldn't be parsed or is obfuscated.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    header_values(headers, &header::FORWARDED)
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+        })
+        .collect()
+}
+
+/// Parses an address which can have a port (ex: "192.0.2.43:80" or "[2001:db8::1]:4711").
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn trusted_proxies() -> TrustedProxies {
+        TrustedProxies::new(
+            vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()],
+            false,
+        )
+    }
+
+    fn headers(headers: &[(&HeaderName, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_untrusted_peer_is_the_client() {
+        let peer = "203.0.113.1:1234".parse().unwrap();
+        let spoofed = headers(&[
+            (&CF_CONNECTING_IP_HEADER, "1.1.1.1"),
+            (&X_FORWARDED_FOR_HEADER, "1.1.1.1"),
+        ]);
+
+        assert_eq!(
+            trusted_proxies().resolve(peer, &spoofed),
+            "203.0.113.1".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            TrustedProxies::default().resolve("10.0.0.1:1234".parse().unwrap(), &spoofed),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_forwarded_chains() {
+        let proxies = trusted_proxies();
+        let peer: SocketAddr = "10.0.0.2:1234".parse().unwrap();
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        // the client can prepend any address, but the trusted proxy appends the real one
+        let x_forwarded_for = headers(&[
+            (&X_FORWARDED_FOR_HEADER, "1.1.1.1, 198.51.100.7"),
+            (&X_FORWARDED_FOR_HEADER, "10.0.0.1"),
+        ]);
+        assert_eq!(proxies.resolve(peer, &x_forwarded_for), ip("198.51.100.7"));
+
+        let forwarded = headers(&[(
+            &header::FORWARDED,
+            r#"for=1.1.1.1, for="[2001:db8:cafe::17]:4711";proto=https, For=10.0.0.1:80"#,
+        )]);
+        assert_eq!(proxies.resolve(peer, &forwarded), ip("2001:db8:cafe::17"));
+
+        let obfuscated = headers(&[(&header::FORWARDED, "for=_hidden, for=10.0.0.1")]);
+        assert_eq!(proxies.resolve(peer, &obfuscated), ip("10.0.0.1"));
+
+        // only trusted when the requests come through cloudflare
+        let cloudflare = headers(&[
+            (&CF_CONNECTING_IP_HEADER, "2001:db8::1"),
+            (&X_FORWARDED_FOR_HEADER, "198.51.100.7"),
+        ]);
+        assert_eq!(proxies.resolve(peer, &cloudflare), ip("198.51.100.7"));
+        let cloudflare_proxies = TrustedProxies::new(proxies.cidrs.to_vec(), true);
+        assert_eq!(
+            cloudflare_proxies.resolve(peer, &cloudflare),
+            ip("2001:db8::1")
+        );
+
+        assert_eq!(proxies.resolve(peer, &HeaderMap::new()), ip("10.0.0.2"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The validators of a representation, which clients send back in conditional requests
+/// (`If-None-Match` or `If-Modified-Since`) to get a `304 Not Modified` if it didn't change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    /// A weak entity tag (ex: `W/"1a2b3c"`), since the same version can be serialized differently
+    etag: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// Creates the validators of the representation identified by `version`.
+    pub fn new(version: impl Hash, last_modified: DateTime<Utc>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        version.hash(&mut hasher);
+
+        Self {
+            etag: format!("W/\"{:x}\"", hasher.finish()),
+            last_modified,
+        }
+    }
+
+    /// Whether both identify the same version, regardless of when it was last modified.
+    pub fn same_version(&self, other: &Validators) -> bool {
+        self.etag == other.etag
+    }
+
+    /// Whether the client already has this version, according to the conditional headers
+    /// of its request, where `If-Modified-Since` is ignored if `If-None-Match` is sent.
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if headers.contains_key(header::IF_NONE_MATCH) {
+            return headers
+                .get_all(header::IF_NONE_MATCH)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .any(|etag| etag == "*" || weak_eq(etag, &self.etag));
+        }
+
+        headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            // the header has a precision of seconds
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        // clients can keep the representation, but must validate it before using it
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+
+        let last_modified = self.last_modified.format(HTTP_DATE_FORMAT).to_string();
+        if let Ok(last_modified) = HeaderValue::from_str(&last_modified) {
+            headers.insert(header::LAST_MODIFIED, last_modified);
+        }
+    }
+
+    pub fn not_modified(&self) -> Response {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        self.insert_headers(response.headers_mut());
+        response
+    }
+
+    /// Sends `body` with the validators, or `304 Not Modified` if the client already has it.
+    pub fn respond(&self, request_headers: &HeaderMap, body: impl IntoResponse) -> Response {
+        if self.is_fresh(request_headers) {
+            return self.not_modified();
+        }
+
+        let mut response = body.into_response();
+        self.insert_headers(response.headers_mut());
+        response
+    }
+}
+
+/// Compares entity tags ignoring whether they are weak, as required for `If-None-Match`.
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let last_modified = Utc.with_ymd_and_hms(2026, 10, 19, 12, 30, 0).unwrap();
+        let validators = Validators::new((1, "version"), last_modified);
+
+        let mut response_headers = HeaderMap::new();
+        validators.insert_headers(&mut response_headers);
+        assert_eq!(
+            response_headers[header::LAST_MODIFIED],
+            "Mon, 19 Oct 2026 12:30:00 GMT"
+        );
+
+        let etag = validators.etag.clone();
+        let strong_etag = etag.trim_start_matches("W/");
+        assert!(validators.is_fresh(&headers(header::IF_NONE_MATCH, &etag)));
+        assert!(validators.is_fresh(&headers(
+            header::IF_NONE_MATCH,
+            &format!("\"other\", {strong_etag}")
+        )));
+        assert!(validators.is_fresh(&headers(header::IF_NONE_MATCH, "*")));
+        assert!(!validators.is_fresh(&headers(header::IF_NONE_MATCH, "W/\"other\"")));
+
+        let since = |date| headers(header::IF_MODIFIED_SINCE, date);
+        assert!(validators.is_fresh(&since("Mon, 19 Oct 2026 12:30:00 GMT")));
+        assert!(!validators.is_fresh(&since("Mon, 19 Oct 2026 12:29:59 GMT")));
+        assert!(!validators.is_fresh(&since("invalid")));
+        assert!(!validators.is_fresh(&HeaderMap::new()));
+
+        let mut both = since("Mon, 19 Oct 2026 12:30:00 GMT");
+        both.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!validators.is_fresh(&both));
+    }
+}
@@ -0,0 +1,83 @@
+use axum::{
+    extract::{OriginalUri, Request},
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::NaiveDate;
+
+const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// When the unversioned `/api` routes were deprecated in favor of `/api/v1`.
+const UNVERSIONED_DEPRECATED_AT: NaiveDate = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+/// Deprecated routes keep working for at least 6 months after being deprecated.
+const UNVERSIONED_SUNSET_AT: NaiveDate = NaiveDate::from_ymd_opt(2027, 4, 19).unwrap();
+
+/// The prefixes of the deprecated routes and of the routes replacing them.
+const UNVERSIONED_PREFIX: &str = "/api/";
+const SUCCESSOR_PREFIX: &str = "/api/v1/";
+
+/// The `Deprecation` header as in RFC 9745, which is the unix timestamp of the deprecation.
+fn deprecation_header(date: NaiveDate) -> HeaderValue {
+    let timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    HeaderValue::from_str(&format!("@{timestamp}")).unwrap()
+}
+
+/// The `Sunset` header as in RFC 8594, which is the HTTP date when the route is removed.
+fn sunset_header(date: NaiveDate) -> HeaderValue {
+    let date = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    HeaderValue::from_str(&date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).unwrap()
+}
+
+/// The path of the v1 route replacing an unversioned route.
+fn successor_path(path: &str) -> Option<String> {
+    path.strip_prefix(UNVERSIONED_PREFIX)
+        .map(|rest| format!("{SUCCESSOR_PREFIX}{rest}"))
+}
+
+/// Marks the responses of the unversioned routes, which are aliases of the v1 routes
+/// kept while clients migrate, as deprecated and links to the route replacing them.
+pub async fn unversioned_api_layer(request: Request, next: Next) -> Response {
+    // the routes are nested, so the path of the request doesn't have the prefix
+    let path = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path(),
+        None => request.uri().path(),
+    };
+    let successor = successor_path(path);
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        DEPRECATION_HEADER,
+        deprecation_header(UNVERSIONED_DEPRECATED_AT),
+    );
+    headers.insert(SUNSET_HEADER, sunset_header(UNVERSIONED_SUNSET_AT));
+
+    if let Some(link) = successor
+        .map(|path| format!("<{path}>; rel=\"successor-version\""))
+        .and_then(|link| HeaderValue::from_str(&link).ok())
+    {
+        headers.insert(header::LINK, link);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deprecation_headers() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        assert_eq!(deprecation_header(date), "@1792368000");
+        assert_eq!(sunset_header(date), "Mon, 19 Oct 2026 00:00:00 GMT");
+
+        assert_eq!(
+            successor_path("/api/contract/1").as_deref(),
+            Some("/api/v1/contract/1")
+        );
+        assert_eq!(successor_path("/other"), None);
+    }
+}
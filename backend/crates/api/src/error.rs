@@ -1,57 +1,164 @@
-use axum::{http::Response, response::IntoResponse};
+use std::time::Duration;
+
+use axum::{
+    http::{HeaderValue, Response, header},
+    response::IntoResponse,
+};
 use reqwest::StatusCode;
 use serde::Serialize;
 use tracing::error;
+use utoipa::ToSchema;
+
+use crate::request_id;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
-    #[error(transparent)]
+    #[error("A failure from Meilisearch has occurred")]
     MeilisearchError(#[from] meilisearch_sdk::errors::Error),
     #[error("Invalid JSON: {0}")]
     JsonParseError(String),
-    #[error(transparent)]
+    #[error("Invalid query string: {0}")]
+    QueryStringParseError(String),
+    #[error("Invalid filters: {0}")]
+    InvalidFilters(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("Invalid pagination: {0}")]
+    InvalidPagination(String),
+    #[error("Invalid search settings: {0}")]
+    InvalidSearchSettings(String),
+    #[error("Search settings were updated, the current version is {0}")]
+    SearchSettingsConflict(u32),
+    #[error("Invalid or missing admin token")]
+    Unauthorized,
+    #[error("Invalid or revoked API key")]
+    InvalidApiKey,
+    #[error("An internal error has occurred")]
+    InternalError(#[from] anyhow::Error),
+    #[error("Semantic search is not enabled")]
+    SemanticSearchDisabled,
+    #[error("Contract {0} not found")]
+    ContractNotFound(u64),
+    #[error("Route not found")]
+    RouteNotFound,
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+    #[error("A failure from Database has occurred")]
     DatabaseError(#[from] sqlx::Error),
     #[error("Could not determine client IP address")]
     MissingClientIp,
     #[error("Too many requests")]
-    RateLimited,
+    RateLimited { retry_after: Duration },
+    #[error("The scraper is not enabled in this backend")]
+    ScraperDisabled,
+    #[error("A {0} is already running")]
+    AlreadyRunning(&'static str),
 }
 
 pub type AppResult<T> = Result<T, AppError>;
 
-#[derive(Debug, Serialize)]
-struct ErrorBody {
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    /// A stable identifier of the error, so that clients don't depend on the messages.
+    code: &'static str,
     message: String,
+    /// The id of the request, which is also in the logs of the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::MeilisearchError(_) => "search_failure",
+            AppError::JsonParseError(_) => "invalid_json",
+            AppError::QueryStringParseError(_) => "invalid_query_string",
+            AppError::InvalidFilters(_) => "invalid_filters",
+            AppError::InvalidQuery(_) => "invalid_query",
+            AppError::InvalidPagination(_) => "invalid_pagination",
+            AppError::InvalidSearchSettings(_) => "invalid_search_settings",
+            AppError::SearchSettingsConflict(_) => "search_settings_conflict",
+            AppError::Unauthorized => "unauthorized",
+            AppError::InvalidApiKey => "invalid_api_key",
+            AppError::InternalError(_) => "internal_error",
+            AppError::SemanticSearchDisabled => "semantic_search_disabled",
+            AppError::ContractNotFound(_) => "contract_not_found",
+            AppError::RouteNotFound => "route_not_found",
+            AppError::MethodNotAllowed => "method_not_allowed",
+            AppError::DatabaseError(_) => "database_failure",
+            AppError::MissingClientIp => "missing_client_ip",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::ScraperDisabled => "scraper_disabled",
+            AppError::AlreadyRunning(_) => "already_running",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::JsonParseError(_)
+            | AppError::QueryStringParseError(_)
+            | AppError::InvalidFilters(_)
+            | AppError::InvalidQuery(_)
+            | AppError::InvalidPagination(_)
+            | AppError::InvalidSearchSettings(_)
+            | AppError::SemanticSearchDisabled
+            | AppError::ScraperDisabled => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized | AppError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            AppError::ContractNotFound(_) | AppError::RouteNotFound => StatusCode::NOT_FOUND,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::SearchSettingsConflict(_) | AppError::AlreadyRunning(_) => {
+                StatusCode::CONFLICT
+            }
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::MeilisearchError(_)
+            | AppError::InternalError(_)
+            | AppError::DatabaseError(_)
+            | AppError::MissingClientIp => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response<axum::body::Body> {
-        let (error_code, message) = match self {
-            AppError::MeilisearchError(e) => {
-                error!("Meilisearch error: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("A failure from Meilisearch has occurred"),
-                )
-            }
-            AppError::JsonParseError(message) => (
-                StatusCode::BAD_REQUEST,
-                format!("Invalid JSON: {}", message),
-            ),
-            AppError::DatabaseError(e) => {
-                error!("Database error: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("A failure from Database has occurred"),
-                )
-            }
-            AppError::MissingClientIp => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Could not determine client IP address"),
-            ),
-            AppError::RateLimited => (StatusCode::TOO_MANY_REQUESTS, format!("Too many requests")),
+        match &self {
+            AppError::MeilisearchError(e) => error!("Meilisearch error: {:?}", e),
+            AppError::InternalError(e) => error!("Internal error: {:?}", e),
+            AppError::DatabaseError(e) => error!("Database error: {:?}", e),
+            _ => {}
+        }
+
+        let error_body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            request_id: request_id::current(),
         };
-        let error_body = ErrorBody { message };
-        (error_code, axum::Json(error_body)).into_response()
+        let mut response = (self.status(), axum::Json(error_body)).into_response();
+
+        if let AppError::RateLimited { retry_after } = self {
+            // rounded up, so that clients don't retry before the limit is lifted
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limited_response() {
+        let response = AppError::RateLimited {
+            retry_after: Duration::from_millis(1500),
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
     }
 }
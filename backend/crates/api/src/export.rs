@@ -0,0 +1,328 @@
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use common::{Currency, Entity, SearchableContract};
+use futures::stream;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, AppResult},
+    state::AppState,
+    v1,
+};
+
+/// Contracts fetched from Meilisearch for each chunk of the exported file.
+const EXPORT_BATCH_SIZE: usize = 1000;
+/// Contracts that an export with a query can include, since it is paged by offset, which gets
+/// slower the deeper it goes.
+const MAX_QUERY_EXPORT_HITS: usize = 10000;
+/// Separator used to join the values of list fields in a single CSV cell.
+const LIST_SEPARATOR: &str = " | ";
+/// Columns of the exported CSV, in the order of the fields of [ExportRow].
+const CSV_HEADER: [&str; 20] = [
+    "id",
+    "publicationDate",
+    "signingDate",
+    "closeDate",
+    "contractingProcedureType",
+    "contractTypes",
+    "objectBriefDescription",
+    "initialContractualPrice",
+    "totalEffectivePrice",
+    "contractingNifs",
+    "contractingNames",
+    "contractedNifs",
+    "contractedNames",
+    "cpvCodes",
+    "cpvDesignations",
+    "executionPlaces",
+    "regime",
+    "ccp",
+    "contestantsCount",
+    "contractingProcedureUrl",
+];
+
+/// Where the next batch of an export starts.
+#[derive(Debug, Clone, Copy)]
+pub enum ExportPosition {
+    /// Without a query, contracts are sorted by id, so the next batch is filtered after the
+    /// last id, which is cheaper than an offset.
+    AfterId(Option<u64>),
+    /// With a query, the relevance ranks before the sort, so the results are paged by offset
+    /// instead, up to [MAX_QUERY_EXPORT_HITS].
+    Offset(usize),
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// A contract flattened into a single row, where lists are joined into a single value.
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(Default))]
+#[serde(rename_all = "camelCase")]
+struct ExportRow {
+    id: u64,
+    publication_date: NaiveDate,
+    signing_date: Option<NaiveDate>,
+    close_date: Option<NaiveDate>,
+    contracting_procedure_type: String,
+    contract_types: String,
+    object_brief_description: String,
+    /// In euros
+    initial_contractual_price: String,
+    /// In euros
+    total_effective_price: Option<String>,
+    contracting_nifs: String,
+    contracting_names: String,
+    contracted_nifs: String,
+    contracted_names: String,
+    cpv_codes: String,
+    cpv_designations: String,
+    execution_places: String,
+    regime: Option<String>,
+    ccp: bool,
+    contestants_count: usize,
+    contracting_procedure_url: Option<String>,
+}
+
+fn format_euros(Currency(cents): &Currency) -> String {
+    let sign = if *cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{sign}{}.{:02}", cents / 100, cents % 100)
+}
+
+fn join_entities(entities: &[Entity], field: impl Fn(&Entity) -> &str) -> String {
+    entities.iter().map(field).join(LIST_SEPARATOR)
+}
+
+impl From<SearchableContract> for ExportRow {
+    fn from(contract: SearchableContract) -> Self {
+        ExportRow {
+            id: contract.id,
+            publication_date: contract.publication_date,
+            signing_date: contract.signing_date,
+            close_date: contract.close_date,
+            contracting_procedure_type: contract.contracting_procedure_type,
+            contract_types: contract.contract_types,
+            object_brief_description: contract.object_brief_description,
+            initial_contractual_price: format_euros(&contract.initial_contractual_price),
+            total_effective_price: contract.total_effective_price.as_ref().map(format_euros),
+            contracting_nifs: join_entities(&contract.contracting, |e| &e.nif),
+            contracting_names: join_entities(&contract.contracting, |e| &e.description),
+            contracted_nifs: join_entities(&contract.contracted, |e| &e.nif),
+            contracted_names: join_entities(&contract.contracted, |e| &e.description),
+            cpv_codes: contract
+                .cpvs
+                .iter()
+                .map(|cpv| &cpv.code)
+                .join(LIST_SEPARATOR),
+            cpv_designations: contract
+                .cpvs
+                .iter()
+                .map(|cpv| &cpv.designation)
+                .join(LIST_SEPARATOR),
+            execution_places: contract.execution_places.join(LIST_SEPARATOR),
+            regime: contract.regime,
+            ccp: contract.ccp,
+            contestants_count: contract.contestants_count,
+            contracting_procedure_url: contract.contracting_procedure_url,
+        }
+    }
+}
+
+fn encode_batch(
+    format: ExportFormat,
+    contracts: Vec<SearchableContract>,
+    with_headers: bool,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => {
+            // the header is written apart, so that it is there even without contracts
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+
+            if with_headers {
+                writer.write_record(CSV_HEADER)?;
+            }
+
+            for contract in contracts {
+                writer.serialize(ExportRow::from(contract))?;
+            }
+
+            Ok(writer.into_inner()?)
+        }
+        ExportFormat::Jsonl => {
+            let mut buffer = Vec::new();
+            for contract in contracts {
+                serde_json::to_writer(&mut buffer, &v1::ContractSummary::from(contract))?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+struct ExportCursor {
+    state: AppState,
+    query: String,
+    filters: Vec<String>,
+    attributes_to_search_on: Vec<&'static str>,
+    format: ExportFormat,
+    position: ExportPosition,
+    with_headers: bool,
+    done: bool,
+}
+
+impl ExportCursor {
+    async fn next_chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let limit = match self.position {
+            ExportPosition::AfterId(_) => EXPORT_BATCH_SIZE,
+            ExportPosition::Offset(offset) => EXPORT_BATCH_SIZE.min(MAX_QUERY_EXPORT_HITS - offset),
+        };
+
+        let contracts = self
+            .state
+            .search_for_export(
+                &self.query,
+                &self.filters,
+                &self.attributes_to_search_on,
+                self.position,
+                limit,
+            )
+            .await?;
+
+        self.done = contracts.len() < EXPORT_BATCH_SIZE;
+        self.position = match self.position {
+            ExportPosition::AfterId(after_id) => {
+                ExportPosition::AfterId(contracts.last().map(|last| last.id).or(after_id))
+            }
+            ExportPosition::Offset(offset) => {
+                let offset = offset + contracts.len();
+                self.done |= offset >= MAX_QUERY_EXPORT_HITS;
+                ExportPosition::Offset(offset)
+            }
+        };
+
+        if contracts.is_empty() && !self.with_headers {
+            return Ok(None);
+        }
+
+        let chunk = encode_batch(self.format, contracts, self.with_headers)?;
+        self.with_headers = false;
+        Ok(Some(Bytes::from(chunk)))
+    }
+}
+
+/// Streams every contract matching the query and filters, in ascending id order, or by relevance
+/// when there is a query, which can match at most [MAX_QUERY_EXPORT_HITS] contracts.
+pub async fn export_response(
+    state: AppState,
+    query: String,
+    filters: Vec<String>,
+    attributes_to_search_on: Vec<&'static str>,
+    format: ExportFormat,
+) -> AppResult<Response> {
+    let position = if query.trim().is_empty() {
+        ExportPosition::AfterId(None)
+    } else {
+        // checked before the response starts, so that it is rejected instead of cut short
+        let total = state
+            .count_for_export(&query, &filters, &attributes_to_search_on)
+            .await?;
+        if total > MAX_QUERY_EXPORT_HITS {
+            return Err(AppError::InvalidQuery(format!(
+                "exports with a query can include at most {MAX_QUERY_EXPORT_HITS} contracts, \
+                 but it matches {total}, so it must be narrowed (ex: with filters)"
+            )));
+        }
+
+        ExportPosition::Offset(0)
+    };
+
+    let cursor = ExportCursor {
+        state,
+        query,
+        filters,
+        attributes_to_search_on,
+        format,
+        position,
+        with_headers: true,
+        done: false,
+    };
+
+    let stream = stream::unfold(cursor, |mut cursor| async move {
+        match cursor.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), cursor)),
+            Ok(None) => None,
+            Err(e) => {
+                // the response has already started, so the stream is aborted
+                error!("Failed to export contracts: {e:?}");
+                cursor.done = true;
+                Some((Err(e), cursor))
+            }
+        }
+    });
+
+    let content_disposition = format!("attachment; filename=\"contratos.{}\"", format.extension());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_header_matches_rows() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(ExportRow::default()).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(csv.lines().next(), Some(CSV_HEADER.join(",").as_str()));
+    }
+
+    #[test]
+    fn test_empty_csv_has_header() {
+        let csv = encode_batch(ExportFormat::Csv, vec![], true).unwrap();
+
+        assert_eq!(String::from_utf8(csv).unwrap(), CSV_HEADER.join(",") + "\n");
+    }
+}
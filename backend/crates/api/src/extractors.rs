@@ -1,13 +1,16 @@
 use std::net::{IpAddr, SocketAddr};
 
 use axum::{
-    extract::{ConnectInfo, FromRequest, FromRequestParts, rejection::JsonRejection},
+    extract::{
+        ConnectInfo, FromRequest, FromRequestParts,
+        rejection::{JsonRejection, QueryRejection},
+    },
     http::request::Parts,
     response::IntoResponse,
 };
 use serde::Serialize;
 
-use crate::error::AppError;
+use crate::{client_ip::TrustedProxies, error::AppError};
 
 #[derive(FromRequest)]
 #[from_request(via(axum::Json), rejection(AppError))]
@@ -26,8 +29,18 @@ impl<T: Serialize> IntoResponse for Json<T> {
     }
 }
 
-const CF_CONNECTING_IP_HEADER: &str = "CF-Connecting-IP";
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct Query<T>(pub T);
 
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::QueryStringParseError(rejection.body_text())
+    }
+}
+
+/// The IP of the client, resolved through the [TrustedProxies] in the request extensions.
+/// Without them, the forwarding headers are ignored and the peer is the client.
 pub struct ClientIp(pub IpAddr);
 
 impl<S> FromRequestParts<S> for ClientIp
@@ -37,19 +50,15 @@ where
     type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let cf_ip = parts
-            .headers
-            .get(CF_CONNECTING_IP_HEADER)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.parse::<IpAddr>().ok())
-            .map(ClientIp);
-
-        match cf_ip {
-            Some(ip) => Ok(ip),
-            None => ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
-                .await
-                .map(|ConnectInfo(addr)| ClientIp(addr.ip()))
-                .map_err(|_| AppError::MissingClientIp),
-        }
+        let ConnectInfo(peer) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| AppError::MissingClientIp)?;
+
+        let ip = match parts.extensions.get::<TrustedProxies>() {
+            Some(trusted_proxies) => trusted_proxies.resolve(peer, &parts.headers),
+            None => peer.ip().to_canonical(),
+        };
+
+        Ok(ClientIp(ip))
     }
 }
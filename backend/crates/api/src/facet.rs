@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use meilisearch_sdk::search::FacetStats;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Max values returned for the facets with an unbounded number of values (ex: entities).
+const MAX_TOP_VALUES: usize = 10;
+const PRICE_ATTRIBUTE: &str = "initialContractualPrice";
+
+#[derive(Debug)]
+pub enum FacetField {
+    ContractingProcedureType,
+    ContractTypes,
+    PublicationYear,
+    CpvDivision,
+    District,
+    Contracting,
+    Contracted,
+}
+
+impl FacetField {
+    const ALL: [FacetField; 7] = [
+        Self::ContractingProcedureType,
+        Self::ContractTypes,
+        Self::PublicationYear,
+        Self::CpvDivision,
+        Self::District,
+        Self::Contracting,
+        Self::Contracted,
+    ];
+
+    pub fn to_meilisearch(&self) -> &'static str {
+        match self {
+            FacetField::ContractingProcedureType => "contractingProcedureType",
+            FacetField::ContractTypes => "contractTypes",
+            FacetField::PublicationYear => "publicationYear",
+            FacetField::CpvDivision => "cpvDivisions",
+            FacetField::District => "districts",
+            FacetField::Contracting => "contracting.description",
+            FacetField::Contracted => "contracted.description",
+        }
+    }
+
+    /// The attributes that must be filterable for the facets to be computed.
+    pub fn to_meilisearch_all() -> Vec<&'static str> {
+        Self::ALL.iter().map(Self::to_meilisearch).collect()
+    }
+
+    /// The attributes to request in a search to compute every facet, including the price range.
+    pub fn to_meilisearch_query() -> Vec<&'static str> {
+        let mut attributes = Self::to_meilisearch_all();
+        attributes.push(PRICE_ATTRIBUTE);
+        attributes
+    }
+
+    fn max_values(&self) -> Option<usize> {
+        match self {
+            FacetField::Contracting | FacetField::Contracted => Some(MAX_TOP_VALUES),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Facets {
+    pub contracting_procedure_type: Vec<FacetValue>,
+    pub contract_types: Vec<FacetValue>,
+    pub publication_year: Vec<FacetValue>,
+    pub cpv_division: Vec<FacetValue>,
+    pub district: Vec<FacetValue>,
+    pub contracting: Vec<FacetValue>,
+    pub contracted: Vec<FacetValue>,
+    pub price: Option<PriceRange>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FacetValue {
+    pub value: String,
+    pub count: usize,
+}
+
+/// The price range (in cents) of the contracts matching the current query and filters.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Facets {
+    pub fn from_meilisearch(
+        mut distribution: HashMap<String, HashMap<String, usize>>,
+        stats: &HashMap<String, FacetStats>,
+    ) -> Self {
+        let mut values = |field: FacetField| {
+            let mut values: Vec<FacetValue> = distribution
+                .remove(field.to_meilisearch())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(value, count)| FacetValue { value, count })
+                .collect();
+
+            values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+            if let Some(max_values) = field.max_values() {
+                values.truncate(max_values);
+            }
+            values
+        };
+
+        Facets {
+            contracting_procedure_type: values(FacetField::ContractingProcedureType),
+            contract_types: values(FacetField::ContractTypes),
+            publication_year: values(FacetField::PublicationYear),
+            cpv_division: values(FacetField::CpvDivision),
+            district: values(FacetField::District),
+            contracting: values(FacetField::Contracting),
+            contracted: values(FacetField::Contracted),
+            price: stats.get(PRICE_ATTRIBUTE).map(|stats| PriceRange {
+                min: stats.min as i64,
+                max: stats.max as i64,
+            }),
+        }
+    }
+}
@@ -1,7 +1,16 @@
 use chrono::NaiveDate;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::{
+    PartialSchema, ToSchema,
+    openapi::{ArrayBuilder, OneOfBuilder, RefOr, Schema},
+};
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+/// Max values that can be sent in a single filter field.
+pub const MAX_VALUES_PER_FIELD: usize = 20;
+/// Max values that can be sent across all filter fields (including excluded ones).
+pub const MAX_TOTAL_VALUES: usize = 50;
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Filters {
     #[serde(default)]
@@ -17,13 +26,185 @@ pub struct Filters {
     #[serde(default)]
     pub end_signing_date: Option<NaiveDate>,
     #[serde(default)]
-    pub contracted: Option<String>,
+    pub start_close_date: Option<NaiveDate>,
     #[serde(default)]
-    pub contracting: Option<String>,
+    pub end_close_date: Option<NaiveDate>,
     #[serde(default)]
     pub min_price: Option<i64>,
     #[serde(default)]
     pub max_price: Option<i64>,
+    #[serde(default)]
+    pub min_effective_price: Option<i64>,
+    #[serde(default)]
+    pub max_effective_price: Option<i64>,
+    #[serde(default)]
+    pub ccp: Option<bool>,
+    #[serde(default)]
+    pub min_contestants: Option<usize>,
+    #[serde(default)]
+    pub max_contestants: Option<usize>,
+    #[serde(default)]
+    pub has_documents: Option<bool>,
+    #[serde(flatten)]
+    pub values: ValueFilters,
+    /// Contracts matching any of these values are excluded from the results.
+    #[serde(default)]
+    pub exclude: ValueFilters,
+}
+
+/// The filters that match contracts against a list of values.
+/// A contract matches a field if it matches any of its values.
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ValueFilters {
+    #[schema(value_type = FilterValues)]
+    pub contracted: Values<String>,
+    #[schema(value_type = FilterValues)]
+    pub contracting: Values<String>,
+    /// Matches the NIF of either the contracting or the contracted entities.
+    #[schema(value_type = FilterValues)]
+    pub nif: Values<String>,
+    #[schema(value_type = FilterValues)]
+    pub contracting_procedure_type: Values<String>,
+    #[schema(value_type = FilterValues)]
+    pub contract_types: Values<String>,
+    #[schema(value_type = FilterValues)]
+    pub regime: Values<String>,
+    /// Matches either a full CPV code or a CPV prefix (division, group, class or category).
+    #[schema(value_type = FilterValues)]
+    pub cpv: Values<String>,
+    /// Matches either a full execution place or its district.
+    #[schema(value_type = FilterValues)]
+    pub execution_place: Values<String>,
+}
+
+/// A list of values that can also be deserialized from a single value.
+#[derive(Debug, Serialize, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Values<T>(pub Vec<T>);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Values<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany<T> {
+            One(T),
+            Many(Vec<T>),
+        }
+
+        Ok(match Option::<OneOrMany<T>>::deserialize(deserializer)? {
+            None => Values(Vec::new()),
+            Some(OneOrMany::One(value)) => Values(vec![value]),
+            Some(OneOrMany::Many(values)) => Values(values),
+        })
+    }
+}
+
+/// The OpenAPI schema of [Values] of strings.
+pub struct FilterValues;
+
+impl PartialSchema for FilterValues {
+    fn schema() -> RefOr<Schema> {
+        OneOfBuilder::new()
+            .item(String::schema())
+            .item(ArrayBuilder::new().items(String::schema()))
+            .into()
+    }
+}
+
+impl ToSchema for FilterValues {}
+
+impl<T> Values<T> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl<T> From<Vec<T>> for Values<T> {
+    fn from(values: Vec<T>) -> Self {
+        Values(values)
+    }
+}
+
+impl ValueFilters {
+    /// The meilisearch attributes each field is matched against.
+    fn fields(&self) -> [(&'static str, &'static [&'static str], &Values<String>); 8] {
+        [
+            (
+                "contracted",
+                &["contracted.description", "contracted.nif"],
+                &self.contracted,
+            ),
+            (
+                "contracting",
+                &["contracting.description", "contracting.nif"],
+                &self.contracting,
+            ),
+            ("nif", &["contracting.nif", "contracted.nif"], &self.nif),
+            (
+                "contractingProcedureType",
+                &["contractingProcedureType"],
+                &self.contracting_procedure_type,
+            ),
+            ("contractTypes", &["contractTypes"], &self.contract_types),
+            ("regime", &["regime"], &self.regime),
+            ("cpv", &["cpvs.code", "cpvPrefixes"], &self.cpv),
+            (
+                "executionPlace",
+                &["executionPlaces", "districts"],
+                &self.execution_place,
+            ),
+        ]
+    }
+
+    fn total_values(&self) -> usize {
+        self.fields()
+            .iter()
+            .map(|(_, _, values)| values.len())
+            .sum()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, _, values) in self.fields() {
+            if values.len() > MAX_VALUES_PER_FIELD {
+                return Err(format!(
+                    "'{name}' has {} values, the maximum is {MAX_VALUES_PER_FIELD}",
+                    values.len()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn to_meilisearch(&self, exclude: bool) -> Vec<String> {
+        self.fields()
+            .into_iter()
+            .filter(|(_, _, values)| !values.is_empty())
+            .map(|(_, attributes, values)| {
+                let values = Filters::format_values(&values.0);
+
+                let condition = attributes
+                    .iter()
+                    .map(|attribute| format!("{attribute} IN [{values}]"))
+                    .collect::<Vec<_>>()
+                    .join(" OR ");
+
+                if exclude {
+                    format!("NOT ({condition})")
+                } else {
+                    format!("({condition})")
+                }
+            })
+            .collect()
+    }
 }
 
 impl Filters {
@@ -32,14 +213,50 @@ impl Filters {
             "id",
             "publicationDate",
             "signingDate",
+            "closeDate",
             "contracted",
             "contracting",
             "initialContractualPrice",
+            "totalEffectivePrice",
+            "contractingProcedureType",
+            "contractTypes",
+            "regime",
+            "ccp",
+            "cpvs.code",
+            "cpvPrefixes",
+            "executionPlaces",
+            "districts",
+            "contestantsCount",
+            "hasDocuments",
         ]
     }
 
     fn escape_string_value(value: &str) -> String {
-        value.replace('\'', "\\'")
+        value.replace('\\', "\\\\").replace('\'', "\\'")
+    }
+
+    /// Formats the values as the escaped, comma separated contents of a meilisearch `IN [...]` list.
+    pub fn format_values(values: &[String]) -> String {
+        values
+            .iter()
+            .map(|value| format!("'{}'", Self::escape_string_value(value)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Checks the limits of values that can be sent in the filters.
+    pub fn validate(&self) -> Result<(), String> {
+        self.values.validate()?;
+        self.exclude.validate()?;
+
+        let total_values = self.values.total_values() + self.exclude.total_values();
+        if total_values > MAX_TOTAL_VALUES {
+            return Err(format!(
+                "{total_values} filter values were sent, the maximum is {MAX_TOTAL_VALUES}"
+            ));
+        }
+
+        Ok(())
     }
 
     pub fn to_meilisearch(&self) -> Vec<String> {
@@ -63,17 +280,11 @@ impl Filters {
         if let Some(end_date) = self.end_signing_date {
             filters.push(format!("signingDate <= '{end_date}'"));
         }
-        if let Some(entity) = &self.contracted {
-            let escaped = Self::escape_string_value(entity);
-            filters.push(format!(
-                "contracted.description = '{escaped}' OR contracted.nif = '{escaped}'"
-            ));
+        if let Some(start_date) = self.start_close_date {
+            filters.push(format!("closeDate >= '{start_date}'"));
         }
-        if let Some(entity) = &self.contracting {
-            let escaped = Self::escape_string_value(entity);
-            filters.push(format!(
-                "contracting.description = '{escaped}' OR contracting.nif = '{escaped}'"
-            ));
+        if let Some(end_date) = self.end_close_date {
+            filters.push(format!("closeDate <= '{end_date}'"));
         }
         if let Some(price) = self.min_price {
             filters.push(format!("initialContractualPrice >= {price}"));
@@ -81,7 +292,84 @@ impl Filters {
         if let Some(price) = self.max_price {
             filters.push(format!("initialContractualPrice <= {price}"));
         }
+        if let Some(price) = self.min_effective_price {
+            filters.push(format!("totalEffectivePrice >= {price}"));
+        }
+        if let Some(price) = self.max_effective_price {
+            filters.push(format!("totalEffectivePrice <= {price}"));
+        }
+        if let Some(ccp) = self.ccp {
+            filters.push(format!("ccp = {ccp}"));
+        }
+        if let Some(contestants) = self.min_contestants {
+            filters.push(format!("contestantsCount >= {contestants}"));
+        }
+        if let Some(contestants) = self.max_contestants {
+            filters.push(format!("contestantsCount <= {contestants}"));
+        }
+        if let Some(has_documents) = self.has_documents {
+            filters.push(format!("hasDocuments = {has_documents}"));
+        }
+
+        filters.extend(self.values.to_meilisearch(false));
+        filters.extend(self.exclude.to_meilisearch(true));
 
         filters
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_and_multiple_values() {
+        let filters: Filters = serde_json::from_str(
+            r#"{
+                "contracting": "500000000",
+                "contractingProcedureType": ["Ajuste Direto", "Consulta Prévia"],
+                "exclude": { "contracted": ["Serviço Nacional de Saúde"] }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            filters.to_meilisearch(),
+            vec![
+                "(contracting.description IN ['500000000'] OR contracting.nif IN ['500000000'])",
+                "(contractingProcedureType IN ['Ajuste Direto', 'Consulta Prévia'])",
+                "NOT (contracted.description IN ['Serviço Nacional de Saúde'] OR contracted.nif IN ['Serviço Nacional de Saúde'])",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_string_value() {
+        let filters = Filters {
+            values: ValueFilters {
+                regime: vec![r"a'b\".to_string()].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(filters.to_meilisearch(), vec![r"(regime IN ['a\'b\\'])"]);
+    }
+
+    #[test]
+    fn test_validate_limits() {
+        let values = |count: usize| Values((0..count).map(|i| i.to_string()).collect());
+
+        let mut filters = Filters::default();
+        filters.values.cpv = values(MAX_VALUES_PER_FIELD);
+        assert!(filters.validate().is_ok());
+
+        filters.values.cpv = values(MAX_VALUES_PER_FIELD + 1);
+        assert!(filters.validate().is_err());
+
+        filters.values.cpv = values(MAX_VALUES_PER_FIELD);
+        filters.values.regime = values(MAX_VALUES_PER_FIELD);
+        filters.exclude.contracted = values(MAX_VALUES_PER_FIELD);
+        assert!(filters.validate().is_err());
+    }
+}
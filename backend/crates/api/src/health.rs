@@ -0,0 +1,250 @@
+use std::{future::Future, time::Duration};
+
+use axum::{Router, extract::State, http::StatusCode, routing::get};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::error;
+
+use crate::{error::AppResult, extractors::Json, state::AppState};
+
+/// How long a dependency has to answer before it is considered down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// The statistics are reloaded every 15 minutes, so they are stale if a few reloads failed.
+const MAX_STATISTICS_AGE: chrono::Duration = chrono::Duration::hours(1);
+/// Stale contracts are still served correctly, so an old scrape only warns.
+const MAX_SCRAPE_AGE: chrono::Duration = chrono::Duration::days(1);
+
+/// The health routes, outside of `/api` since they are meant for the orchestrator.
+pub fn health_router() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+enum CheckStatus {
+    Pass,
+    /// Degraded, but still able to serve traffic
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Check {
+    status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_millis: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_secs: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Check {
+    fn new(status: CheckStatus) -> Self {
+        Self {
+            status,
+            latency_millis: None,
+            age_secs: None,
+            message: None,
+        }
+    }
+
+    fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Checks a dependency by calling it, failing if it errors or doesn't answer in time.
+    /// The errors are logged instead of returned, since they may contain internal details.
+    async fn call<T>(
+        name: &str,
+        check: impl Future<Output = AppResult<T>>,
+        status: impl FnOnce(T) -> Check,
+    ) -> Check {
+        let start = Instant::now();
+        let result = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+        let latency_millis = Some(start.elapsed().as_millis() as u64);
+
+        let check = match result {
+            Ok(Ok(value)) => status(value),
+            Ok(Err(e)) => {
+                error!(
+                    "Readiness check of {name} failed: {:#}",
+                    anyhow::Error::from(e)
+                );
+                Check::new(CheckStatus::Fail).with_message("Unavailable")
+            }
+            Err(_) => Check::new(CheckStatus::Fail)
+                .with_message(format!("Timed out after {CHECK_TIMEOUT:?}")),
+        };
+
+        Check {
+            latency_millis,
+            ..check
+        }
+    }
+
+    /// Checks the age of something that is periodically updated.
+    fn age(
+        updated_at: Option<DateTime<Utc>>,
+        max_age: chrono::Duration,
+        stale: CheckStatus,
+    ) -> Self {
+        let Some(updated_at) = updated_at else {
+            return Check::new(stale).with_message("Never updated");
+        };
+
+        let age = Utc::now() - updated_at;
+        let check = if age > max_age {
+            Check::new(stale).with_message(format!("Older than {}", format_duration(max_age)))
+        } else {
+            Check::new(CheckStatus::Pass)
+        };
+
+        Check {
+            age_secs: Some(age.num_seconds()),
+            ..check
+        }
+    }
+}
+
+/// Formats the duration in the largest whole unit (ex: "1 day", "90 minutes").
+fn format_duration(duration: chrono::Duration) -> String {
+    let (value, unit) = if duration.num_days() > 0
+        && duration == chrono::Duration::days(duration.num_days())
+    {
+        (duration.num_days(), "day")
+    } else if duration.num_hours() > 0 && duration == chrono::Duration::hours(duration.num_hours())
+    {
+        (duration.num_hours(), "hour")
+    } else {
+        (duration.num_minutes(), "minute")
+    };
+
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{value} {unit}{plural}")
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadinessChecks {
+    postgres: Check,
+    meilisearch: Check,
+    statistics: Check,
+    scraper: Check,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Readiness {
+    status: CheckStatus,
+    checks: ReadinessChecks,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Liveness {
+    status: CheckStatus,
+}
+
+/// The process is up, regardless of its dependencies.
+#[axum::debug_handler]
+async fn healthz() -> Json<Liveness> {
+    Json(Liveness {
+        status: CheckStatus::Pass,
+    })
+}
+
+/// Whether the backend can serve traffic, which it can't while the search index is empty
+/// or missing (ex: during a rebuild) or its dependencies are down.
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let (postgres, meilisearch, scraper) = tokio::join!(
+        Check::call("postgres", state.ping_contract_database(), |_| {
+            Check::new(CheckStatus::Pass)
+        }),
+        Check::call(
+            "meilisearch",
+            state.count_indexed_contracts(),
+            |documents| {
+                if documents == 0 {
+                    Check::new(CheckStatus::Fail).with_message("The contracts index is empty")
+                } else {
+                    Check::new(CheckStatus::Pass)
+                }
+            }
+        ),
+        Check::call(
+            "scraper",
+            state.get_last_successful_scrape(),
+            |scraped_at| { Check::age(scraped_at, MAX_SCRAPE_AGE, CheckStatus::Warn) }
+        ),
+    );
+
+    let statistics = Check::age(
+        state.get_statistics().reloaded_at,
+        MAX_STATISTICS_AGE,
+        CheckStatus::Fail,
+    );
+
+    let checks = ReadinessChecks {
+        postgres,
+        meilisearch,
+        statistics,
+        scraper,
+    };
+
+    let status = [
+        &checks.postgres,
+        &checks.meilisearch,
+        &checks.statistics,
+        &checks.scraper,
+    ]
+    .iter()
+    .map(|check| check.status)
+    .max()
+    .unwrap_or(CheckStatus::Pass);
+
+    let status_code = if status == CheckStatus::Fail {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+
+    (status_code, Json(Readiness { status, checks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age_check() {
+        let max_age = chrono::Duration::hours(1);
+
+        let fresh = Check::age(Some(Utc::now()), max_age, CheckStatus::Fail);
+        assert_eq!(fresh.status, CheckStatus::Pass);
+        assert_eq!(fresh.age_secs, Some(0));
+
+        let stale = Utc::now() - chrono::Duration::hours(2);
+        let stale = Check::age(Some(stale), max_age, CheckStatus::Warn);
+        assert_eq!(stale.status, CheckStatus::Warn);
+        assert_eq!(stale.message.as_deref(), Some("Older than 1 hour"));
+
+        let never = Check::age(None, max_age, CheckStatus::Fail);
+        assert_eq!(never.status, CheckStatus::Fail);
+        assert_eq!(never.age_secs, None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(chrono::Duration::days(1)), "1 day");
+        assert_eq!(format_duration(chrono::Duration::hours(2)), "2 hours");
+        assert_eq!(format_duration(chrono::Duration::minutes(90)), "90 minutes");
+    }
+}
@@ -1,25 +1,52 @@
-use crate::state::AppState;
+use crate::{
+    client_ip::TrustedProxies, rate_limit_policy::RateLimitPolicies,
+    rate_limit_store::RateLimitStoreKind, recent_errors::RecentErrors, router::RouterConfig,
+    scraper_task::ScraperHandle, state::AppState,
+};
 use anyhow::Context;
 use clap::Parser;
 use common::{
     db::{ContractDatabase, PostgresConfig},
+    embedder::EmbedderConfig,
     searchdb::{MeilisearchConfig, SearchDatabase},
 };
+use ipnet::IpNet;
 use reqwest::Url;
-use scraper::{base_gov::client::BaseGovClient, store::Store};
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use scraper::store::Store;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::signal;
-use tracing::{Level, event, info};
-
+use tracing::{Level, event, info, level_filters::LevelFilter};
+use tracing_subscriber::{Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+mod admin;
+mod api_key;
+mod client_ip;
+mod conditional;
+mod deprecation;
 mod error;
+mod export;
 mod extractors;
+mod facet;
 mod filter;
+mod health;
 mod metrics;
+mod openapi;
+mod pagination;
+mod query;
 mod rate_limit;
+mod rate_limit_policy;
+mod rate_limit_store;
+mod recent_errors;
+mod request_id;
 mod router;
+mod scraper_task;
+mod search_cache;
+mod search_settings;
+mod similar;
 mod sort;
 mod state;
 mod statistics;
+mod v1;
 
 #[derive(Parser)]
 struct Args {
@@ -29,12 +56,38 @@ struct Args {
     metrics_bind_url: String,
     #[clap(long, env, default_value = "60")]
     scraper_interval_secs: u64,
+    /// How often Portal BASE is polled for new contracts, besides the scrape of every contract.
+    #[clap(long, env, default_value = "60")]
+    poll_interval_secs: u64,
+    /// How often a batch of the open contracts is fetched again, to save the fields filled in
+    /// when they are closed.
+    #[clap(long, env, default_value = "600")]
+    refresh_interval_secs: u64,
     #[clap(long, env, default_value = "../data/scraper/saved_pages.json")]
     saved_pages_path: PathBuf,
     #[clap(flatten)]
     meilisearch_config: MeilisearchConfig,
     #[clap(flatten)]
     postgres_config: PostgresConfig,
+    #[clap(flatten)]
+    embedder_config: EmbedderConfig,
+    /// Enables the admin routes, which require this token as a bearer token.
+    #[clap(long, env)]
+    admin_token: Option<String>,
+    /// The rate limit policies of the routes, which default to `rate-limits.json`.
+    #[clap(long, env)]
+    rate_limits_path: Option<PathBuf>,
+    /// Where the rate limits are kept, which must be postgres to share them between replicas.
+    #[clap(long, env, value_enum, default_value = "memory")]
+    rate_limit_store: RateLimitStoreKind,
+    /// The CIDRs of the proxies in front of the backend (ex: "172.16.0.0/12,10.0.0.0/8"),
+    /// whose forwarding headers are trusted to find the IP of the client.
+    #[clap(long, env, value_delimiter = ',')]
+    trusted_proxies: Vec<IpNet>,
+    /// Trusts the `CF-Connecting-IP` header sent by the trusted proxies,
+    /// which must only be set if every request comes through Cloudflare.
+    #[clap(long, env)]
+    trust_cf_connecting_ip: bool,
     #[clap(long, env)]
     no_scraper: bool,
     #[clap(long, env)]
@@ -43,7 +96,11 @@ struct Args {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::fmt().init();
+    let recent_errors = RecentErrors::new();
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(LevelFilter::INFO))
+        .with(recent_errors.clone())
+        .init();
 
     let args = Args::parse();
 
@@ -59,27 +116,56 @@ async fn main() -> anyhow::Result<()> {
         .context("Failed to create scraper store")?,
     );
 
-    let app_state = AppState::new(search_database, contract_database);
+    let rate_limit_store = args.rate_limit_store.create(contract_database.clone());
+
+    let mut app_state = AppState::new(
+        search_database.clone(),
+        contract_database.clone(),
+        args.embedder_config.create_embedder(),
+    );
     app_state
         .prepare_settings()
         .await
         .context("Failed to prepare indexes")?;
 
-    if !args.no_scraper {
-        tokio::spawn(async move {
-            loop {
-                let base_gov_client = BaseGovClient::new(args.base_gov_client_proxy.clone());
-                scraper::scraper::scrape(scraper_store.clone(), base_gov_client).await;
-                tokio::time::sleep(tokio::time::Duration::from_secs(args.scraper_interval_secs))
-                    .await;
-            }
-        });
-    }
+    let scraper = ScraperHandle::new(
+        scraper_store,
+        search_database.clone(),
+        contract_database.clone(),
+        args.base_gov_client_proxy,
+        !args.no_scraper,
+    );
+    tokio::spawn(
+        scraper
+            .clone()
+            .run_scrape_task(Duration::from_secs(args.scraper_interval_secs)),
+    );
+    tokio::spawn(
+        scraper
+            .clone()
+            .run_poll_task(Duration::from_secs(args.poll_interval_secs)),
+    );
+    tokio::spawn(
+        scraper
+            .clone()
+            .run_refresh_task(Duration::from_secs(args.refresh_interval_secs)),
+    );
 
     tokio::spawn(statistics::run_reload_statistics_task(app_state.clone()));
-
-    let backend_router =
-        router::router(app_state).into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(api_key::run_flush_usage_task(app_state.clone()));
+    tokio::spawn(search_cache::run_invalidate_cache_task(app_state.clone()));
+    tokio::spawn(rate_limit_store::run_cleanup_task(rate_limit_store.clone()));
+
+    let router_config = RouterConfig {
+        admin_token: args.admin_token,
+        trusted_proxies: TrustedProxies::new(args.trusted_proxies, args.trust_cf_connecting_ip),
+        rate_limit_policies: RateLimitPolicies::load(args.rate_limits_path.as_deref())?,
+        rate_limit_store,
+        scraper,
+        recent_errors,
+    };
+    let backend_router = router::router(app_state, router_config)
+        .into_make_service_with_connect_info::<SocketAddr>();
 
     let backend_listener = tokio::net::TcpListener::bind(args.bind_url)
         .await
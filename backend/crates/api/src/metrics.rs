@@ -15,6 +15,8 @@ fn setup_metrics_recorder() -> anyhow::Result<PrometheusHandle> {
     const EXPONENTIAL_SECONDS: &[f64] = &[
         0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
     ];
+    // up to the timeout of the client
+    const PORTAL_BASE_SECONDS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
 
     PrometheusBuilder::new()
         .set_buckets_for_metric(
@@ -22,6 +24,11 @@ fn setup_metrics_recorder() -> anyhow::Result<PrometheusHandle> {
             EXPONENTIAL_SECONDS,
         )
         .context("Couldn't create buckets")?
+        .set_buckets_for_metric(
+            Matcher::Full(scraper::base_gov::client::REQUEST_DURATION_METRIC.to_string()),
+            PORTAL_BASE_SECONDS,
+        )
+        .context("Couldn't create buckets")?
         .install_recorder()
         .context("Couldn't install recorder")
 }
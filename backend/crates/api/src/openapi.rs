@@ -0,0 +1,83 @@
+use std::sync::LazyLock;
+
+use axum::{http::header, response::IntoResponse};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+};
+
+use crate::{api_key::API_KEY_HEADER, router};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Contrato Público API",
+        description = "Search the public contracts published in Portal BASE"
+    ),
+    paths(
+        router::search,
+        router::search_get,
+        router::export,
+        router::contract,
+        router::similar_contracts,
+        router::suggest_entities,
+        router::statistics,
+    ),
+    modifiers(&ApiKeySecurity),
+    // the API key is optional, anonymous clients are rate limited by IP
+    security((), ("apiKey" = []))
+)]
+pub struct ApiDoc;
+
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "apiKey",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                API_KEY_HEADER.as_str(),
+                "Rate limits the client by its key instead of its IP, with the quota of its tier",
+            ))),
+        );
+    }
+}
+
+static OPENAPI_SPEC: LazyLock<String> = LazyLock::new(|| {
+    ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("OpenAPI spec should serialize")
+});
+
+pub async fn openapi_spec() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        OPENAPI_SPEC.as_str(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// Fails when the API types change without committing the new spec.
+    /// Run with `UPDATE_OPENAPI=1` to update it.
+    #[test]
+    fn test_openapi_spec_is_up_to_date() {
+        let spec = format!("{}\n", OPENAPI_SPEC.as_str());
+
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SPEC_PATH, &spec).unwrap();
+            return;
+        }
+
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "{SPEC_PATH} is outdated, run `UPDATE_OPENAPI=1 cargo test -p backend openapi` and commit it"
+        );
+    }
+}
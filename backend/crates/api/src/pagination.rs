@@ -0,0 +1,118 @@
+pub const DEFAULT_HITS_PER_PAGE: usize = 20;
+pub const MAX_HITS_PER_PAGE: usize = 100;
+/// Max position of the last requested hit, since deep pages are slow to compute.
+pub const MAX_RESULT_WINDOW: usize = 100_000;
+
+/// How the search results are paginated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pagination {
+    /// Numbered pages (starting at 1) with the exhaustive number of hits and pages.
+    Page { page: usize, hits_per_page: usize },
+    /// An arbitrary window of the hits, with an estimate of the number of hits.
+    OffsetLimit { offset: usize, limit: usize },
+}
+
+impl Pagination {
+    /// Validates the pagination parameters of a search, where `offset` and `limit`
+    /// can't be mixed with `page` and `hits_per_page`.
+    pub fn from_params(
+        page: Option<usize>,
+        hits_per_page: Option<usize>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<Self, String> {
+        let pagination = if offset.is_some() || limit.is_some() {
+            if page.is_some() || hits_per_page.is_some() {
+                return Err(
+                    "'offset' and 'limit' can't be used with 'page' and 'hitsPerPage'".to_string(),
+                );
+            }
+
+            Pagination::OffsetLimit {
+                offset: offset.unwrap_or(0),
+                limit: limit.unwrap_or(DEFAULT_HITS_PER_PAGE),
+            }
+        } else {
+            Pagination::Page {
+                page: page.unwrap_or(1),
+                hits_per_page: hits_per_page.unwrap_or(DEFAULT_HITS_PER_PAGE),
+            }
+        };
+
+        pagination.validate()?;
+        Ok(pagination)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (name, size) = match self {
+            Pagination::Page { hits_per_page, .. } => ("hitsPerPage", *hits_per_page),
+            Pagination::OffsetLimit { limit, .. } => ("limit", *limit),
+        };
+
+        if !(1..=MAX_HITS_PER_PAGE).contains(&size) {
+            return Err(format!(
+                "'{name}' must be between 1 and {MAX_HITS_PER_PAGE}"
+            ));
+        }
+
+        if let Pagination::Page { page: 0, .. } = self {
+            return Err("'page' must be at least 1".to_string());
+        }
+
+        if self.end() > MAX_RESULT_WINDOW {
+            return Err(format!(
+                "Only the first {MAX_RESULT_WINDOW} results can be requested"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The position after the last requested hit.
+    fn end(&self) -> usize {
+        match *self {
+            Pagination::Page {
+                page,
+                hits_per_page,
+            } => page.saturating_mul(hits_per_page),
+            Pagination::OffsetLimit { offset, limit } => offset.saturating_add(limit),
+        }
+    }
+
+    pub fn hits_per_page(&self) -> usize {
+        match *self {
+            Pagination::Page { hits_per_page, .. } => hits_per_page,
+            Pagination::OffsetLimit { limit, .. } => limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination_params() {
+        assert_eq!(
+            Pagination::from_params(None, None, None, None),
+            Ok(Pagination::Page {
+                page: 1,
+                hits_per_page: DEFAULT_HITS_PER_PAGE
+            })
+        );
+        assert_eq!(
+            Pagination::from_params(None, None, Some(40), Some(10)),
+            Ok(Pagination::OffsetLimit {
+                offset: 40,
+                limit: 10
+            })
+        );
+
+        assert!(Pagination::from_params(Some(0), None, None, None).is_err());
+        assert!(Pagination::from_params(None, Some(0), None, None).is_err());
+        assert!(Pagination::from_params(None, Some(MAX_HITS_PER_PAGE + 1), None, None).is_err());
+        assert!(Pagination::from_params(Some(1), None, Some(0), None).is_err());
+        assert!(Pagination::from_params(Some(usize::MAX), None, None, None).is_err());
+        assert!(Pagination::from_params(None, None, Some(MAX_RESULT_WINDOW), None).is_err());
+    }
+}
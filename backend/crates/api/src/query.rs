@@ -0,0 +1,513 @@
+//! Parser for the advanced search syntax typed in the search box.
+//!
+//! Example: `nif:500000000 cpv:72* price:>100000 year:2024 "software licensing" -microsoft`
+//!
+//! Filter operators (`nif:`, `contracting:`, `contracted:`, `cpv:`, `place:`, `price:`, `year:`)
+//! are moved into [Filters]. Value operators can be negated with `-` to exclude their values.
+//! Field operators (`title:`, `entity:`) are kept in the text but restrict the search to their
+//! attributes. Quoted phrases and negated words are kept as they are, since Meilisearch supports them.
+
+use chrono::NaiveDate;
+use itertools::Itertools;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::filter::Filters;
+
+/// Max characters of a query, including its operators.
+pub const MAX_QUERY_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedQuery {
+    /// The text query sent to Meilisearch, without the filter operators.
+    pub text: String,
+    /// The attributes the text query is restricted to, if any field operator was used.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes_to_search_on: Vec<&'static str>,
+    pub tokens: Vec<QueryToken>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum QueryToken {
+    Term {
+        value: String,
+    },
+    Phrase {
+        value: String,
+    },
+    Negation {
+        value: String,
+    },
+    Operator {
+        operator: &'static str,
+        value: String,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum QueryParseError {
+    #[error("Unterminated quote")]
+    UnterminatedQuote,
+    #[error("Missing value for '{0}:'")]
+    MissingValue(&'static str),
+    #[error("Invalid value '{value}' for '{operator}:'")]
+    InvalidValue {
+        operator: &'static str,
+        value: String,
+    },
+    #[error("'{0}:' cannot be negated")]
+    CannotNegate(&'static str),
+    #[error("Query has {0} characters, the maximum is {MAX_QUERY_LENGTH}")]
+    TooLong(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Nif,
+    Contracting,
+    Contracted,
+    Cpv,
+    Place,
+    Price,
+    Year,
+    Title,
+    Entity,
+}
+
+impl Operator {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "nif" => Some(Self::Nif),
+            "contracting" => Some(Self::Contracting),
+            "contracted" => Some(Self::Contracted),
+            "cpv" => Some(Self::Cpv),
+            "place" => Some(Self::Place),
+            "price" => Some(Self::Price),
+            "year" => Some(Self::Year),
+            "title" => Some(Self::Title),
+            "entity" => Some(Self::Entity),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Nif => "nif",
+            Self::Contracting => "contracting",
+            Self::Contracted => "contracted",
+            Self::Cpv => "cpv",
+            Self::Place => "place",
+            Self::Price => "price",
+            Self::Year => "year",
+            Self::Title => "title",
+            Self::Entity => "entity",
+        }
+    }
+
+    /// The attributes searched by field operators.
+    fn search_attributes(self) -> &'static [&'static str] {
+        match self {
+            Self::Title => &["objectBriefDescription"],
+            Self::Entity => &["contracting.description", "contracted.description"],
+            _ => &[],
+        }
+    }
+}
+
+/// A raw token split from the query, before interpreting operators.
+#[derive(Debug, PartialEq, Eq)]
+struct RawToken {
+    negated: bool,
+    /// The operator name, if the token is in the `name:value` form.
+    name: Option<String>,
+    value: String,
+    quoted: bool,
+}
+
+fn tokenize(query: &str) -> Result<Vec<RawToken>, QueryParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    fn read_quoted(
+        chars: &mut std::iter::Peekable<std::str::Chars>,
+    ) -> Result<String, QueryParseError> {
+        let mut value = String::new();
+        for c in chars.by_ref() {
+            if c == '"' {
+                return Ok(value);
+            }
+            value.push(c);
+        }
+        Err(QueryParseError::UnterminatedQuote)
+    }
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let negated = c == '-';
+        if negated {
+            chars.next();
+        }
+
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let value = read_quoted(&mut chars)?;
+            tokens.push(RawToken {
+                negated,
+                name: None,
+                value,
+                quoted: true,
+            });
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            word.push(c);
+
+            if c == ':' && chars.peek() == Some(&'"') {
+                chars.next();
+                let name = word.trim_end_matches(':').to_string();
+                let value = read_quoted(&mut chars)?;
+                tokens.push(RawToken {
+                    negated,
+                    name: Some(name),
+                    value,
+                    quoted: true,
+                });
+                word.clear();
+                break;
+            }
+        }
+
+        if word.is_empty() {
+            continue;
+        }
+
+        let token = match word.split_once(':') {
+            Some((name, value)) => RawToken {
+                negated,
+                name: Some(name.to_string()),
+                value: value.to_string(),
+                quoted: false,
+            },
+            None => RawToken {
+                negated,
+                name: None,
+                value: word,
+                quoted: false,
+            },
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an amount in euros (ex: `1000`, `1000,50` or `1000.50`) into cents.
+fn parse_cents(value: &str) -> Option<i64> {
+    let euros: f64 = value.replace(',', ".").parse().ok()?;
+    euros.is_finite().then(|| (euros * 100.0).round() as i64)
+}
+
+/// Parses a range value: `>x`, `>=x`, `<x`, `<=x`, `x..y` or `x`.
+/// Returns the inclusive bounds, where exclusive bounds are adjusted by `step`.
+fn parse_range<T>(
+    value: &str,
+    parse: impl Fn(&str) -> Option<T>,
+    step: impl Fn(T, i64) -> Option<T>,
+) -> Option<(Option<T>, Option<T>)>
+where
+    T: Copy,
+{
+    if let Some(min) = value.strip_prefix(">=") {
+        Some((Some(parse(min)?), None))
+    } else if let Some(min) = value.strip_prefix('>') {
+        Some((Some(step(parse(min)?, 1)?), None))
+    } else if let Some(max) = value.strip_prefix("<=") {
+        Some((None, Some(parse(max)?)))
+    } else if let Some(max) = value.strip_prefix('<') {
+        Some((None, Some(step(parse(max)?, -1)?)))
+    } else if let Some((min, max)) = value.split_once("..") {
+        // open ranges (ex: `2020..`) only have one of the bounds
+        let bound = |value: &str| match value {
+            "" => Some(None),
+            value => parse(value).map(Some),
+        };
+        Some((bound(min)?, bound(max)?))
+    } else {
+        let exact = parse(value)?;
+        Some((Some(exact), Some(exact)))
+    }
+}
+
+/// Narrows the bounds of a filter with the ones of an operator, so that an operator never
+/// widens the filters that were set explicitly.
+fn intersect<T: Ord>(
+    (min, max): (Option<T>, Option<T>),
+    (other_min, other_max): (Option<T>, Option<T>),
+) -> (Option<T>, Option<T>) {
+    // a missing minimum is the smallest one
+    let min = min.max(other_min);
+    let max = match (max, other_max) {
+        (Some(max), Some(other_max)) => Some(max.min(other_max)),
+        (max, other_max) => max.or(other_max),
+    };
+
+    (min, max)
+}
+
+fn quote_if_needed(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{value}\"")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parses the query, moving its filter operators into `filters`.
+pub fn parse_query(query: &str, filters: &mut Filters) -> Result<ParsedQuery, QueryParseError> {
+    let length = query.chars().count();
+    if length > MAX_QUERY_LENGTH {
+        return Err(QueryParseError::TooLong(length));
+    }
+
+    let mut text = Vec::new();
+    let mut attributes_to_search_on = Vec::new();
+    let mut tokens = Vec::new();
+
+    for token in tokenize(query)? {
+        let operator = token.name.as_deref().and_then(Operator::from_name);
+
+        let Some(operator) = operator else {
+            // unknown operators (ex: urls) are searched as plain text
+            let value = match &token.name {
+                Some(name) => format!("{name}:{}", token.value),
+                None => token.value,
+            };
+
+            let (search_text, query_token) = match (token.negated, token.quoted) {
+                (true, true) => (format!("-\"{value}\""), QueryToken::Negation { value }),
+                (true, false) => (format!("-{value}"), QueryToken::Negation { value }),
+                (false, true) => (format!("\"{value}\""), QueryToken::Phrase { value }),
+                (false, false) => (value.clone(), QueryToken::Term { value }),
+            };
+
+            text.push(search_text);
+            tokens.push(query_token);
+            continue;
+        };
+
+        let name = operator.name();
+        let value = token.value.trim().to_string();
+        if value.is_empty() {
+            return Err(QueryParseError::MissingValue(name));
+        }
+
+        let invalid_value = || QueryParseError::InvalidValue {
+            operator: name,
+            value: value.clone(),
+        };
+
+        let values = if token.negated {
+            &mut filters.exclude
+        } else {
+            &mut filters.values
+        };
+
+        match operator {
+            Operator::Nif => values.nif.0.push(value.clone()),
+            Operator::Contracting => values.contracting.0.push(value.clone()),
+            Operator::Contracted => values.contracted.0.push(value.clone()),
+            Operator::Place => values.execution_place.0.push(value.clone()),
+            Operator::Cpv => {
+                let prefix = value.trim_end_matches('*');
+                if prefix.is_empty() {
+                    return Err(invalid_value());
+                }
+                values.cpv.0.push(prefix.to_string());
+            }
+            Operator::Price | Operator::Year if token.negated => {
+                return Err(QueryParseError::CannotNegate(name));
+            }
+            Operator::Price => {
+                let range = parse_range(&value, parse_cents, |cents, step| cents.checked_add(step))
+                    .ok_or_else(invalid_value)?;
+                (filters.min_price, filters.max_price) =
+                    intersect((filters.min_price, filters.max_price), range);
+            }
+            Operator::Year => {
+                let (min, max) = parse_range(
+                    &value,
+                    |year| year.parse::<i32>().ok(),
+                    |year, step| year.checked_add(step as i32),
+                )
+                .ok_or_else(invalid_value)?;
+
+                let start = min
+                    .map(|year| NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid_value))
+                    .transpose()?;
+                let end = max
+                    .map(|year| NaiveDate::from_ymd_opt(year, 12, 31).ok_or_else(invalid_value))
+                    .transpose()?;
+                (filters.start_publication_date, filters.end_publication_date) = intersect(
+                    (filters.start_publication_date, filters.end_publication_date),
+                    (start, end),
+                );
+            }
+            Operator::Title | Operator::Entity => {
+                if token.negated {
+                    return Err(QueryParseError::CannotNegate(name));
+                }
+                text.push(quote_if_needed(&value));
+                attributes_to_search_on.extend(operator.search_attributes());
+            }
+        }
+
+        tokens.push(QueryToken::Operator {
+            operator: name,
+            value,
+            negated: token.negated,
+        });
+    }
+
+    // field operators restrict the whole text, so plain terms are also only searched on them
+    let attributes_to_search_on = attributes_to_search_on.into_iter().unique().collect();
+
+    Ok(ParsedQuery {
+        text: text.join(" "),
+        attributes_to_search_on,
+        tokens,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_query() {
+        let mut filters = Filters::default();
+        let parsed = parse_query(
+            r#"nif:500000000 cpv:72* price:>100000 year:2024 "software licensing" -microsoft"#,
+            &mut filters,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.text, r#""software licensing" -microsoft"#);
+        assert!(parsed.attributes_to_search_on.is_empty());
+        assert_eq!(filters.values.nif.0, vec!["500000000"]);
+        assert_eq!(filters.values.cpv.0, vec!["72"]);
+        assert_eq!(filters.min_price, Some(10000001));
+        assert_eq!(filters.max_price, None);
+        assert_eq!(
+            filters.start_publication_date,
+            NaiveDate::from_ymd_opt(2024, 1, 1)
+        );
+        assert_eq!(
+            filters.end_publication_date,
+            NaiveDate::from_ymd_opt(2024, 12, 31)
+        );
+        assert_eq!(parsed.tokens.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_quoted_and_negated_operators() {
+        let mut filters = Filters::default();
+        let parsed = parse_query(
+            r#"-contracting:"Serviço Nacional de Saúde" title:viatura https://base.gov.pt"#,
+            &mut filters,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.text, "viatura https://base.gov.pt");
+        assert_eq!(
+            parsed.attributes_to_search_on,
+            vec!["objectBriefDescription"]
+        );
+        assert_eq!(
+            filters.exclude.contracting.0,
+            vec!["Serviço Nacional de Saúde"]
+        );
+        assert_eq!(
+            parsed.tokens[0],
+            QueryToken::Operator {
+                operator: "contracting",
+                value: "Serviço Nacional de Saúde".to_string(),
+                negated: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let mut filters = Filters::default();
+        parse_query("price:1000,50..2000 year:2020..2022", &mut filters).unwrap();
+
+        assert_eq!(filters.min_price, Some(100050));
+        assert_eq!(filters.max_price, Some(200000));
+        assert_eq!(
+            filters.start_publication_date,
+            NaiveDate::from_ymd_opt(2020, 1, 1)
+        );
+        assert_eq!(
+            filters.end_publication_date,
+            NaiveDate::from_ymd_opt(2022, 12, 31)
+        );
+    }
+
+    #[test]
+    fn test_operators_narrow_filters() {
+        let mut filters = Filters {
+            min_price: Some(150000),
+            max_price: Some(500000),
+            start_publication_date: NaiveDate::from_ymd_opt(2021, 6, 1),
+            ..Default::default()
+        };
+        parse_query("price:1000..2000 year:2020..2022", &mut filters).unwrap();
+
+        assert_eq!(filters.min_price, Some(150000));
+        assert_eq!(filters.max_price, Some(200000));
+        assert_eq!(
+            filters.start_publication_date,
+            NaiveDate::from_ymd_opt(2021, 6, 1)
+        );
+        assert_eq!(
+            filters.end_publication_date,
+            NaiveDate::from_ymd_opt(2022, 12, 31)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |query| parse_query(query, &mut Filters::default()).unwrap_err();
+
+        assert_eq!(
+            parse(r#""unterminated"#),
+            QueryParseError::UnterminatedQuote
+        );
+        assert_eq!(parse("nif:"), QueryParseError::MissingValue("nif"));
+        assert_eq!(
+            parse("price:abc"),
+            QueryParseError::InvalidValue {
+                operator: "price",
+                value: "abc".to_string()
+            }
+        );
+        assert_eq!(parse("-year:2024"), QueryParseError::CannotNegate("year"));
+        assert_eq!(
+            parse(&"a".repeat(MAX_QUERY_LENGTH + 1)),
+            QueryParseError::TooLong(MAX_QUERY_LENGTH + 1)
+        );
+    }
+}
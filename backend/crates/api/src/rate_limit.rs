@@ -1,110 +1,234 @@
 use std::{
     future::Future,
-    net::IpAddr,
+    net::{IpAddr, Ipv6Addr},
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use axum::{
     RequestExt,
     body::Body,
     extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
     response::{IntoResponse, Response},
 };
-use dashmap::DashMap;
-use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use common::api_keys::ApiKeyTier;
 use tower::{Layer, Service};
-use tracing::warn;
+use tracing::{error, warn};
 
-use crate::{error::AppError, extractors::ClientIp};
+use crate::{
+    api_key::ApiKeyIdentity,
+    error::AppError,
+    extractors::ClientIp,
+    rate_limit_policy::{PenaltyPolicy, RateLimitPolicies, RateLimitPolicy, RateLimitedRoute},
+    rate_limit_store::RateLimitStore,
+};
 
-const ENTRIES_FOR_GC: usize = 100;
-const BASE_PENALTY_DURATION: Duration = Duration::from_secs(5);
-const MAX_PENALTY_DURATION: Duration = Duration::from_secs(60);
-const ENTRY_MAP_TTL: Duration = Duration::from_secs(120);
+/// IPv6 clients are usually assigned a whole /64, so they are limited by it instead of by address.
+const IPV6_PREFIX_LENGTH: u32 = 64;
 
-#[derive(Debug)]
-struct PenaltyEntry {
-    until: Instant,
-    map_expires_at: Instant,
-    violations: u32,
+/// Who is rate limited, which is the API key when the client sends one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    Ip(IpAddr),
+    ApiKey(i64),
 }
 
-impl PenaltyEntry {
-    fn is_penalized(&self) -> bool {
-        Instant::now() < self.until
+impl RateLimitKey {
+    fn from_ip(ip: IpAddr) -> Self {
+        let ip = match ip.to_canonical() {
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX << (128 - IPV6_PREFIX_LENGTH);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+            ip => ip,
+        };
+
+        RateLimitKey::Ip(ip)
     }
 
-    fn is_expired(&self) -> bool {
-        Instant::now() >= self.map_expires_at
+    /// The key of the client in the rate limit store.
+    fn store_key(&self) -> String {
+        match self {
+            RateLimitKey::Ip(ip) => format!("ip:{ip}"),
+            RateLimitKey::ApiKey(id) => format!("key:{id}"),
+        }
     }
 }
 
-struct PenaltyMap {
-    inner: DashMap<IpAddr, PenaltyEntry>,
+/// The policy of the API keys of a tier, which allows a multiple of the requests of
+/// anonymous clients. Keys in the unlimited tier have no policy.
+fn tier_policy(policy: RateLimitPolicy, tier: ApiKeyTier) -> Option<RateLimitPolicy> {
+    let multiplier: u32 = match tier {
+        ApiKeyTier::Standard => 5,
+        ApiKeyTier::Research => 20,
+        ApiKeyTier::Unlimited => return None,
+    };
+
+    Some(policy.scaled(multiplier))
 }
 
-impl PenaltyMap {
-    fn new() -> Self {
-        Self {
-            inner: DashMap::new(),
+const RATE_LIMIT_LIMIT_HEADER: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATE_LIMIT_REMAINING_HEADER: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATE_LIMIT_RESET_HEADER: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// The state of the quota of a client after a request, sent in the `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitState {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the quota is fully replenished
+    pub reset: Duration,
+}
+
+impl RateLimitState {
+    fn denied(policy: RateLimitPolicy, retry_after: Duration) -> Self {
+        RateLimitState {
+            limit: policy.burst(),
+            remaining: 0,
+            reset: retry_after,
         }
     }
 
-    fn check(&self, ip: &IpAddr) -> bool {
-        self.inner.get(ip).is_some_and(|entry| entry.is_penalized())
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        // rounded up, so that clients don't retry before the quota is replenished
+        let reset = self.reset.as_secs() + u64::from(self.reset.subsec_nanos() > 0);
+
+        headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(self.limit));
+        headers.insert(
+            RATE_LIMIT_REMAINING_HEADER,
+            HeaderValue::from(self.remaining),
+        );
+        headers.insert(RATE_LIMIT_RESET_HEADER, HeaderValue::from(reset));
     }
+}
 
-    fn penalize(&self, ip: IpAddr) {
-        if self.inner.len() >= ENTRIES_FOR_GC {
-            self.inner.retain(|_, entry| !entry.is_expired());
-        }
+fn rate_limited_response(
+    route: RateLimitedRoute,
+    reason: &'static str,
+    state: RateLimitState,
+) -> Response {
+    metrics::counter!(
+        "rate_limit_rejections_total",
+        "route" => route.as_str(),
+        "reason" => reason
+    )
+    .increment(1);
 
-        let violations = self
-            .inner
-            .get(&ip)
-            .filter(|e| !e.is_expired())
-            .map_or(0, |e| e.violations)
-            + 1;
+    let mut response = AppError::RateLimited {
+        retry_after: state.reset,
+    }
+    .into_response();
+    state.insert_headers(response.headers_mut());
+    response
+}
 
-        let duration = BASE_PENALTY_DURATION
-            .saturating_mul(2u32.saturating_pow(violations - 1))
-            .min(MAX_PENALTY_DURATION);
+/// Creates the rate limit layers of the routes, which share the penalties of the clients,
+/// so that a client penalized on a route is blocked on every route.
+pub struct RateLimits {
+    policies: RateLimitPolicies,
+    store: Arc<dyn RateLimitStore>,
+}
 
-        let now = Instant::now();
+impl RateLimits {
+    pub fn new(policies: RateLimitPolicies, store: Arc<dyn RateLimitStore>) -> Self {
+        Self { policies, store }
+    }
 
-        warn!(
-            ip = %ip,
-            violations,
-            duration_secs = duration.as_secs(),
-            "IP penalized for repeated rate-limit violations"
-        );
+    pub fn layer(&self, route: RateLimitedRoute) -> RateLimitLayer {
+        RateLimitLayer(Arc::new(RouteRateLimiter {
+            route,
+            policy: self.policies.policy(route),
+            penalty: self.policies.penalty,
+            store: self.store.clone(),
+        }))
+    }
+}
 
-        self.inner.insert(
-            ip,
-            PenaltyEntry {
-                until: now + duration,
-                map_expires_at: now + ENTRY_MAP_TTL,
-                violations,
-            },
-        );
+struct RouteRateLimiter {
+    route: RateLimitedRoute,
+    /// The policy of the anonymous clients, which are limited by IP
+    policy: RateLimitPolicy,
+    penalty: PenaltyPolicy,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RouteRateLimiter {
+    /// Checks the request of the client against its penalty and quota,
+    /// returning the state of its quota or the response to send if it is rate limited.
+    /// Requests are allowed if the store fails, so that it doesn't take the API down.
+    async fn check(
+        &self,
+        key: RateLimitKey,
+        policy: RateLimitPolicy,
+    ) -> Result<Option<RateLimitState>, Response> {
+        let client_key = key.store_key();
+        let route = self.route;
+
+        match self.store.penalty(&client_key).await {
+            Ok(Some(retry_after)) => {
+                let state = RateLimitState::denied(policy, retry_after);
+                return Err(rate_limited_response(route, "penalty", state));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to check rate limit penalty: {e:?}");
+                return Ok(None);
+            }
+        }
+
+        let bucket_key = format!("{}:{client_key}", route.as_str());
+        let wait_time = match self.store.check(&bucket_key, policy).await {
+            Ok(Ok(state)) => return Ok(Some(state)),
+            Ok(Err(wait_time)) => wait_time,
+            Err(e) => {
+                error!("Failed to check rate limit: {e:?}");
+                return Ok(None);
+            }
+        };
+
+        let retry_after = match self.store.penalize(&client_key, self.penalty).await {
+            Ok(duration) => {
+                metrics::counter!("rate_limit_penalties_total", "route" => route.as_str())
+                    .increment(1);
+                warn!(
+                    key = ?key,
+                    route = route.as_str(),
+                    duration_secs = duration.as_secs(),
+                    "Client penalized for repeated rate-limit violations"
+                );
+                duration.max(wait_time)
+            }
+            Err(e) => {
+                error!("Failed to penalize client: {e:?}");
+                wait_time
+            }
+        };
+
+        let state = RateLimitState::denied(policy, retry_after);
+        Err(rate_limited_response(route, "limit", state))
     }
 }
 
 #[derive(Clone)]
-pub struct RateLimitLayer {
-    limiter: Arc<DefaultKeyedRateLimiter<IpAddr>>,
-    penalties: Arc<PenaltyMap>,
-}
+pub struct RateLimitLayer(Arc<RouteRateLimiter>);
 
 impl RateLimitLayer {
-    pub fn new(quota: Quota) -> Self {
-        Self {
-            limiter: Arc::new(RateLimiter::keyed(quota)),
-            penalties: Arc::new(PenaltyMap::new()),
-        }
+    /// Checks the request against the quota of its IP, for the requests that are limited before
+    /// reaching the route (ex: the API key lookups), returning the response to send if it is
+    /// rate limited.
+    pub async fn check_ip(&self, request: &mut Request) -> Result<(), Response> {
+        let ClientIp(ip) = request
+            .extract_parts::<ClientIp>()
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        self.0
+            .check(RateLimitKey::from_ip(ip), self.0.policy)
+            .await
+            .map(|_| ())
     }
 }
 
@@ -114,8 +238,7 @@ impl<S> Layer<S> for RateLimitLayer {
     fn layer(&self, inner: S) -> Self::Service {
         RateLimitService {
             inner,
-            limiter: self.limiter.clone(),
-            penalties: self.penalties.clone(),
+            limiter: self.0.clone(),
         }
     }
 }
@@ -123,8 +246,7 @@ impl<S> Layer<S> for RateLimitLayer {
 #[derive(Clone)]
 pub struct RateLimitService<S> {
     inner: S,
-    limiter: Arc<DefaultKeyedRateLimiter<IpAddr>>,
-    penalties: Arc<PenaltyMap>,
+    limiter: Arc<RouteRateLimiter>,
 }
 
 impl<S> Service<Request<Body>> for RateLimitService<S>
@@ -144,24 +266,46 @@ where
     fn call(&mut self, mut request: Request<Body>) -> Self::Future {
         let mut inner = self.inner.clone();
         let limiter = self.limiter.clone();
-        let penalties = self.penalties.clone();
 
         Box::pin(async move {
-            let ClientIp(ip) = match request.extract_parts::<ClientIp>().await {
-                Ok(ip) => ip,
-                Err(e) => return Ok(e.into_response()),
+            // clients with an API key are limited by their key instead of their IP
+            let (key, policy) = match request.extensions().get::<ApiKeyIdentity>() {
+                Some(identity) => match tier_policy(limiter.policy, identity.tier) {
+                    Some(policy) => (RateLimitKey::ApiKey(identity.id), policy),
+                    None => return inner.call(request).await,
+                },
+                None => match request.extract_parts::<ClientIp>().await {
+                    Ok(ClientIp(ip)) => (RateLimitKey::from_ip(ip), limiter.policy),
+                    Err(e) => return Ok(e.into_response()),
+                },
             };
 
-            if penalties.check(&ip) {
-                return Ok(AppError::RateLimited.into_response());
-            }
+            let state = match limiter.check(key, policy).await {
+                Ok(state) => state,
+                Err(response) => return Ok(response),
+            };
 
-            if limiter.check_key(&ip).is_err() {
-                penalties.penalize(ip);
-                return Ok(AppError::RateLimited.into_response());
+            let mut response = inner.call(request).await?;
+            if let Some(state) = state {
+                state.insert_headers(response.headers_mut());
             }
-
-            inner.call(request).await
+            Ok(response)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_key_from_ip() {
+        let key = |ip: &str| RateLimitKey::from_ip(ip.parse().unwrap());
+
+        assert_eq!(key("2001:db8:1:2:aaaa::1"), key("2001:db8:1:2:bbbb::2"));
+        assert_ne!(key("2001:db8:1:2::1"), key("2001:db8:1:3::1"));
+        assert_eq!(key("::ffff:192.0.2.1"), key("192.0.2.1"));
+        assert_ne!(key("192.0.2.1"), key("192.0.2.2"));
+        assert_eq!(key("2001:db8:1:2::1").store_key(), "ip:2001:db8:1:2::");
+    }
+}
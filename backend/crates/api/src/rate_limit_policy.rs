@@ -0,0 +1,199 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// The rate limit policies shipped with the backend, used when no file is configured.
+const DEFAULT_RATE_LIMIT_POLICIES: &str = include_str!("../rate-limits.json");
+
+/// The routes with their own rate limit policy.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitedRoute {
+    Search,
+    Contract,
+    SimilarContracts,
+    /// Requested while typing, so it should allow more requests
+    SuggestEntities,
+    /// Streams every hit of a search, so it is much more expensive than a search
+    Export,
+    Statistics,
+    /// The requests with an API key that isn't cached (ex: an invalid key), limited by IP
+    /// since each one is looked up in the database
+    ApiKeyLookup,
+    /// Every admin route, so that the admin token can't be brute forced
+    Admin,
+}
+
+impl RateLimitedRoute {
+    const ALL: [RateLimitedRoute; 8] = [
+        Self::Search,
+        Self::Contract,
+        Self::SimilarContracts,
+        Self::SuggestEntities,
+        Self::Export,
+        Self::Statistics,
+        Self::ApiKeyLookup,
+        Self::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitedRoute::Search => "search",
+            RateLimitedRoute::Contract => "contract",
+            RateLimitedRoute::SimilarContracts => "similarContracts",
+            RateLimitedRoute::SuggestEntities => "suggestEntities",
+            RateLimitedRoute::Export => "export",
+            RateLimitedRoute::Statistics => "statistics",
+            RateLimitedRoute::ApiKeyLookup => "apiKeyLookup",
+            RateLimitedRoute::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RateLimitPolicies {
+    pub penalty: PenaltyPolicy,
+    pub routes: HashMap<RateLimitedRoute, RateLimitPolicy>,
+}
+
+/// A client is allowed `burst` requests at once, which are replenished one every `period_millis`.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    period_millis: u64,
+    burst: u32,
+}
+
+/// Clients that exceed a rate limit are blocked for `base_secs`, which doubles on every
+/// violation up to `max_secs`, until they go `reset_after_secs` without violations.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PenaltyPolicy {
+    base_secs: u64,
+    max_secs: u64,
+    reset_after_secs: u64,
+}
+
+impl RateLimitPolicies {
+    /// Loads the policies from `path`, or the default policies if no path is configured.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let policies: Self = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).with_context(|| {
+                    format!("Failed to read rate limit policies {}", path.display())
+                })?;
+                serde_json::from_str(&content).with_context(|| {
+                    format!("Failed to parse rate limit policies {}", path.display())
+                })?
+            }
+            None => serde_json::from_str(DEFAULT_RATE_LIMIT_POLICIES)
+                .context("Failed to parse default rate limit policies")?,
+        };
+
+        policies
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid rate limit policies: {e}"))?;
+
+        Ok(policies)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for route in RateLimitedRoute::ALL {
+            let policy = self
+                .routes
+                .get(&route)
+                .ok_or_else(|| format!("missing the policy of '{}'", route.as_str()))?;
+
+            if policy.period_millis == 0 || policy.burst == 0 {
+                return Err(format!(
+                    "the period and burst of '{}' must be greater than 0",
+                    route.as_str()
+                ));
+            }
+        }
+
+        let penalty = &self.penalty;
+        if penalty.base_secs > penalty.max_secs || penalty.max_secs > penalty.reset_after_secs {
+            return Err("the penalty must have base <= max <= reset after durations".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn policy(&self, route: RateLimitedRoute) -> RateLimitPolicy {
+        // validated when loaded
+        self.routes[&route]
+    }
+}
+
+impl RateLimitPolicy {
+    #[cfg(test)]
+    pub fn new(period: Duration, burst: u32) -> Self {
+        Self {
+            period_millis: period.as_millis() as u64,
+            burst,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_millis(self.period_millis)
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// The policy that allows `multiplier` times as many requests.
+    pub fn scaled(&self, multiplier: u32) -> Self {
+        Self {
+            period_millis: (self.period_millis / u64::from(multiplier)).max(1),
+            burst: self.burst.saturating_mul(multiplier),
+        }
+    }
+}
+
+impl PenaltyPolicy {
+    #[cfg(test)]
+    pub fn new(base_secs: u64, max_secs: u64, reset_after_secs: u64) -> Self {
+        Self {
+            base_secs,
+            max_secs,
+            reset_after_secs,
+        }
+    }
+
+    pub fn base_duration(&self) -> Duration {
+        Duration::from_secs(self.base_secs)
+    }
+
+    pub fn max_duration(&self) -> Duration {
+        Duration::from_secs(self.max_secs)
+    }
+
+    pub fn reset_after(&self) -> Duration {
+        Duration::from_secs(self.reset_after_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rate_limit_policies() {
+        let policies = RateLimitPolicies::load(None).unwrap();
+        let policy = policies.policy(RateLimitedRoute::Export);
+        assert_eq!(policy.period(), Duration::from_secs(30));
+        assert_eq!(policy.burst(), 2);
+
+        let scaled = policy.scaled(5);
+        assert_eq!(scaled.period(), Duration::from_secs(6));
+        assert_eq!(scaled.burst(), 10);
+
+        let mut invalid = policies.clone();
+        invalid.routes.remove(&RateLimitedRoute::Statistics);
+        assert!(invalid.validate().is_err());
+    }
+}
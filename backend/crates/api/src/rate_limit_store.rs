@@ -0,0 +1,308 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use common::db::ContractDatabase;
+use dashmap::DashMap;
+use futures::{FutureExt, future::BoxFuture};
+use tracing::error;
+
+use crate::{
+    rate_limit::RateLimitState,
+    rate_limit_policy::{PenaltyPolicy, RateLimitPolicy},
+};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where the rate limits and penalties of the clients are kept.
+/// They must be in a shared store for the limits to hold across replicas of the backend.
+pub trait RateLimitStore: Send + Sync {
+    /// Counts a request of `key` against the policy, returning the state of the quota
+    /// if the request is allowed, or how long until it would be allowed.
+    fn check<'a>(
+        &'a self,
+        key: &'a str,
+        policy: RateLimitPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Result<RateLimitState, Duration>>>;
+
+    /// The remaining duration of the penalty of `key`, if it is penalized.
+    fn penalty<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Duration>>>;
+
+    /// Penalizes `key`, returning the duration of the penalty.
+    fn penalize<'a>(
+        &'a self,
+        key: &'a str,
+        policy: PenaltyPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Duration>>;
+
+    /// Deletes the state that no longer affects the limits (ex: full buckets).
+    fn cleanup(&self) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum RateLimitStoreKind {
+    /// Kept in the memory of the process, for a single replica
+    Memory,
+    /// Kept in Postgres, shared by every replica
+    Postgres,
+}
+
+impl RateLimitStoreKind {
+    pub fn create(self, contract_database: ContractDatabase) -> Arc<dyn RateLimitStore> {
+        match self {
+            RateLimitStoreKind::Memory => Arc::new(MemoryRateLimitStore::new()),
+            RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore(contract_database)),
+        }
+    }
+}
+
+pub async fn run_cleanup_task(store: Arc<dyn RateLimitStore>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.cleanup().await {
+            error!("Failed to clean up rate limits: {e:?}");
+        }
+    }
+}
+
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros() as i64
+}
+
+fn from_micros(micros: i64) -> Duration {
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/// The generic cell rate algorithm, where each request moves the theoretical arrival time
+/// (`tat`) of the next request a period forward, and a request is allowed while the `tat`
+/// is less than `burst` periods ahead. Returns the new `tat` if the request is allowed,
+/// or how long until it would be allowed.
+fn gcra(tat: i64, now: i64, policy: RateLimitPolicy) -> Result<i64, Duration> {
+    let period = micros(policy.period());
+    let new_tat = tat.max(now) + period;
+    let allowed_at = new_tat - period * i64::from(policy.burst());
+
+    if now < allowed_at {
+        Err(from_micros(allowed_at - now))
+    } else {
+        Ok(new_tat)
+    }
+}
+
+/// The state of the quota of a bucket after an allowed request moved its `tat`.
+fn gcra_state(tat: i64, now: i64, policy: RateLimitPolicy) -> RateLimitState {
+    let period = micros(policy.period());
+    let limit = policy.burst();
+    let allowed_at = tat - period * i64::from(limit);
+
+    RateLimitState {
+        limit,
+        remaining: ((now - allowed_at) / period).clamp(0, i64::from(limit)) as u32,
+        reset: from_micros(tat - now),
+    }
+}
+
+/// The duration of the penalty, which doubles on every violation.
+fn penalty_duration(policy: PenaltyPolicy, violations: u32) -> Duration {
+    policy
+        .base_duration()
+        .saturating_mul(2u32.saturating_pow(violations.saturating_sub(1)))
+        .min(policy.max_duration())
+}
+
+#[derive(Debug)]
+struct PenaltyEntry {
+    until: Instant,
+    expires_at: Instant,
+    violations: u32,
+}
+
+pub struct MemoryRateLimitStore {
+    /// The reference of the `tat` of the buckets, which are in microseconds since it
+    start: Instant,
+    buckets: DashMap<String, i64>,
+    penalties: DashMap<String, PenaltyEntry>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            buckets: DashMap::new(),
+            penalties: DashMap::new(),
+        }
+    }
+
+    fn now(&self) -> i64 {
+        micros(self.start.elapsed())
+    }
+}
+
+impl RateLimitStore for MemoryRateLimitStore {
+    fn check<'a>(
+        &'a self,
+        key: &'a str,
+        policy: RateLimitPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Result<RateLimitState, Duration>>> {
+        let now = self.now();
+        // the entry is locked, so that concurrent requests of the same key are counted
+        let mut tat = self.buckets.entry(key.to_string()).or_insert(now);
+
+        let result = gcra(*tat, now, policy).map(|new_tat| {
+            *tat = new_tat;
+            gcra_state(new_tat, now, policy)
+        });
+
+        futures::future::ready(Ok(result)).boxed()
+    }
+
+    fn penalty<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Duration>>> {
+        let remaining = self
+            .penalties
+            .get(key)
+            .and_then(|entry| entry.until.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero());
+
+        futures::future::ready(Ok(remaining)).boxed()
+    }
+
+    fn penalize<'a>(
+        &'a self,
+        key: &'a str,
+        policy: PenaltyPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Duration>> {
+        let now = Instant::now();
+        let mut entry = self
+            .penalties
+            .entry(key.to_string())
+            .or_insert(PenaltyEntry {
+                until: now,
+                expires_at: now,
+                violations: 0,
+            });
+
+        if entry.expires_at <= now {
+            entry.violations = 0;
+        }
+        entry.violations += 1;
+
+        let duration = penalty_duration(policy, entry.violations);
+        entry.until = now + duration;
+        entry.expires_at = now + policy.reset_after();
+
+        futures::future::ready(Ok(duration)).boxed()
+    }
+
+    fn cleanup(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        let now = self.now();
+        self.buckets.retain(|_, tat| *tat > now);
+
+        let instant = Instant::now();
+        self.penalties.retain(|_, entry| entry.expires_at > instant);
+
+        futures::future::ready(Ok(())).boxed()
+    }
+}
+
+pub struct PostgresRateLimitStore(ContractDatabase);
+
+impl RateLimitStore for PostgresRateLimitStore {
+    fn check<'a>(
+        &'a self,
+        key: &'a str,
+        policy: RateLimitPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Result<RateLimitState, Duration>>> {
+        async move {
+            let period = micros(policy.period());
+            let bucket = self
+                .0
+                .check_rate_limit(key, period, i64::from(policy.burst()))
+                .await?;
+
+            let result = if bucket.allowed {
+                Ok(gcra_state(bucket.tat, bucket.now, policy))
+            } else {
+                gcra(bucket.tat, bucket.now, policy).map(|_| RateLimitState {
+                    limit: policy.burst(),
+                    remaining: 0,
+                    reset: from_micros(bucket.tat - bucket.now),
+                })
+            };
+
+            Ok(result)
+        }
+        .boxed()
+    }
+
+    fn penalty<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Duration>>> {
+        async move {
+            let remaining = self.0.get_rate_limit_penalty(key).await?;
+            Ok(remaining.map(from_micros))
+        }
+        .boxed()
+    }
+
+    fn penalize<'a>(
+        &'a self,
+        key: &'a str,
+        policy: PenaltyPolicy,
+    ) -> BoxFuture<'a, anyhow::Result<Duration>> {
+        async move {
+            let violations = self
+                .0
+                .add_rate_limit_violation(key, micros(policy.reset_after()))
+                .await?;
+
+            let duration = penalty_duration(policy, violations.max(1) as u32);
+            self.0.set_rate_limit_penalty(key, micros(duration)).await?;
+
+            Ok(duration)
+        }
+        .boxed()
+    }
+
+    fn cleanup(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            self.0.delete_expired_rate_limits().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_rate_limit_store() {
+        let store = MemoryRateLimitStore::new();
+        let policy = RateLimitPolicy::new(Duration::from_secs(60), 2);
+
+        let first = store.check("search:ip", policy).await.unwrap().unwrap();
+        assert_eq!(first.limit, 2);
+        assert_eq!(first.remaining, 1);
+        assert!(first.reset <= Duration::from_secs(60));
+
+        let second = store.check("search:ip", policy).await.unwrap().unwrap();
+        assert_eq!(second.remaining, 0);
+
+        let wait = store.check("search:ip", policy).await.unwrap().unwrap_err();
+        assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+        assert!(store.check("search:other", policy).await.unwrap().is_ok());
+
+        let penalty = PenaltyPolicy::new(5, 60, 120);
+        assert_eq!(store.penalty("ip").await.unwrap(), None);
+        assert_eq!(
+            store.penalize("ip", penalty).await.unwrap(),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            store.penalize("ip", penalty).await.unwrap(),
+            Duration::from_secs(10)
+        );
+        assert!(store.penalty("ip").await.unwrap().is_some());
+    }
+}
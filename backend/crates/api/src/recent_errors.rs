@@ -0,0 +1,113 @@
+use std::{
+    fmt::{Debug, Write},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use serde::Serialize;
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedError {
+    pub at: DateTime<Utc>,
+    /// The module that logged the error (ex: `scraper::scraper`)
+    pub target: String,
+    pub message: String,
+}
+
+/// The most recent errors logged by the backend and the scraper, so that they can be
+/// checked without access to the logs. The oldest errors are dropped when it is full.
+#[derive(Clone)]
+pub struct RecentErrors(Arc<Mutex<AllocRingBuffer<RecordedError>>>);
+
+impl RecentErrors {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(AllocRingBuffer::new(CAPACITY))))
+    }
+
+    /// The recorded errors, from the most recent.
+    pub fn list(&self) -> Vec<RecordedError> {
+        let errors = self.0.lock().unwrap();
+        errors.iter().rev().cloned().collect()
+    }
+
+    fn record(&self, error: RecordedError) {
+        self.0.lock().unwrap().enqueue(error);
+    }
+}
+
+/// Writes the message of an event followed by its other fields (ex: `message id=1`).
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            // set for the events of the `log` crate
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentErrors {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() != Level::ERROR {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        self.record(RecordedError {
+            at: Utc::now(),
+            target: metadata.target().to_string(),
+            message: visitor.message + &visitor.fields,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{error, info};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn test_recent_errors() {
+        let recent_errors = RecentErrors::new();
+        let subscriber = tracing_subscriber::registry().with(recent_errors.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("Not an error");
+            error!("First error");
+            for id in 0..CAPACITY {
+                error!(id, "Failed to fetch contract");
+            }
+        });
+
+        let errors = recent_errors.list();
+        assert_eq!(errors.len(), CAPACITY);
+        assert_eq!(errors[0].message, "Failed to fetch contract id=99");
+        assert_eq!(
+            errors[CAPACITY - 1].message,
+            "Failed to fetch contract id=0"
+        );
+    }
+}
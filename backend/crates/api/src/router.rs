@@ -1,93 +1,452 @@
-use std::{num::NonZero, time::Duration};
-
 use axum::{
-    Router,
-    extract::{Path, State},
+    Extension, Router,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, header},
     middleware,
-    routing::{get, post},
+    response::{IntoResponse, Response},
+    routing::get,
 };
-use common::{Contract, statistics::Statistics};
-use governor::Quota;
 use serde::Deserialize;
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::{Level, debug};
+use std::sync::Arc;
+use tower_http::trace::TraceLayer;
+use tracing::{Span, debug, info_span};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    error::AppError,
-    extractors::Json,
+    admin, api_key,
+    client_ip::TrustedProxies,
+    conditional::Validators,
+    deprecation,
+    error::{AppError, ErrorBody},
+    export::{self, ExportFormat},
+    extractors::{Json, Query},
     filter::Filters,
-    metrics,
-    rate_limit::RateLimitLayer,
-    sort::SortBy,
-    state::{AppState, SearchResponse},
+    health, metrics, openapi,
+    pagination::Pagination,
+    query,
+    rate_limit::RateLimits,
+    rate_limit_policy::{RateLimitPolicies, RateLimitedRoute},
+    rate_limit_store::RateLimitStore,
+    recent_errors::RecentErrors,
+    request_id,
+    scraper_task::ScraperHandle,
+    sort::{SortBy, SortDirection, SortField},
+    state::{AppState, SearchParams},
+    v1,
 };
 
-pub fn router(app_state: AppState) -> Router {
-    let contract_rate_limit = Quota::with_period(Duration::from_millis(200))
-        .unwrap()
-        .allow_burst(NonZero::try_from(2).unwrap());
+pub struct RouterConfig {
+    /// Enables the admin routes, which require this token as a bearer token.
+    pub admin_token: Option<String>,
+    pub trusted_proxies: TrustedProxies,
+    pub rate_limit_policies: RateLimitPolicies,
+    pub rate_limit_store: Arc<dyn RateLimitStore>,
+    pub scraper: ScraperHandle,
+    pub recent_errors: RecentErrors,
+}
+
+pub fn router(app_state: AppState, config: RouterConfig) -> Router {
+    let rate_limits = RateLimits::new(config.rate_limit_policies, config.rate_limit_store);
+    let rate_limit = |route| rate_limits.layer(route);
+
+    let api_router = Router::new()
+        .route(
+            "/search",
+            get(search_get)
+                .post(search)
+                .route_layer(rate_limit(RateLimitedRoute::Search)),
+        )
+        .route(
+            "/contract/{id}",
+            get(contract).route_layer(rate_limit(RateLimitedRoute::Contract)),
+        )
+        .route(
+            "/contract/{id}/similar",
+            get(similar_contracts).route_layer(rate_limit(RateLimitedRoute::SimilarContracts)),
+        )
+        .route(
+            "/entities/suggest",
+            get(suggest_entities).route_layer(rate_limit(RateLimitedRoute::SuggestEntities)),
+        )
+        .route(
+            "/search/export",
+            get(export).route_layer(rate_limit(RateLimitedRoute::Export)),
+        )
+        .route(
+            "/statistics",
+            get(statistics).route_layer(rate_limit(RateLimitedRoute::Statistics)),
+        )
+        .route("/openapi.json", get(openapi::openapi_spec))
+        .layer(middleware::from_fn_with_state(
+            (
+                app_state.clone(),
+                rate_limit(RateLimitedRoute::ApiKeyLookup),
+            ),
+            api_key::api_key_layer,
+        ));
 
     Router::new()
-        .merge(
-            Router::new()
-                .route("/api/search", post(search))
-                .route("/api/contract/{id}", get(contract))
-                .route_layer(RateLimitLayer::new(contract_rate_limit)),
+        // the rate limiters are shared by both, since the unversioned routes are aliases
+        .nest(
+            "/api",
+            api_router
+                .clone()
+                .layer(middleware::from_fn(deprecation::unversioned_api_layer)),
         )
-        .route("/api/statistics", get(statistics))
+        .nest("/api/v1", api_router)
+        .merge(admin::admin_router(
+            app_state.clone(),
+            config.admin_token,
+            rate_limit(RateLimitedRoute::Admin),
+            config.scraper,
+            config.recent_errors,
+        ))
+        .merge(health::health_router())
+        .fallback(route_not_found)
+        .method_not_allowed_fallback(method_not_allowed)
         .route_layer(middleware::from_fn(metrics::track_metrics_layer))
-        .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::new().level(Level::INFO)))
+        .layer(Extension(config.trusted_proxies))
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+        .layer(middleware::from_fn(request_id::request_id_layer))
         .with_state(app_state)
 }
 
-#[tracing::instrument(skip(state))]
+fn make_request_span(request: &Request) -> Span {
+    info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = request_id::from_request(request),
+    )
+}
+
+async fn route_not_found() -> AppError {
+    AppError::RouteNotFound
+}
+
+async fn method_not_allowed() -> AppError {
+    AppError::MethodNotAllowed
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/statistics",
+    responses(
+        (status = 200, body = v1::Statistics),
+        (status = 304, description = "Not modified since the `If-None-Match` or `If-Modified-Since`"),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
+#[tracing::instrument(skip(state, headers))]
 #[axum::debug_handler]
-pub async fn statistics(State(state): State<AppState>) -> Result<Json<Statistics>, AppError> {
-    Ok(Json(state.get_statistics()))
+pub async fn statistics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let snapshot = state.get_statistics();
+    let statistics: v1::Statistics = snapshot.statistics.into();
+
+    snapshot.validators.respond(&headers, Json(statistics))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct SearchQuery {
     pub query: String,
     pub filters: Option<Filters>,
     pub sort: Option<SortBy>,
     pub page: Option<usize>,
+    pub hits_per_page: Option<usize>,
+    /// Alternative to `page` and `hits_per_page` to request an arbitrary window of the results.
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// Whether to compute the facet distributions of the results.
+    #[serde(default)]
+    pub facets: bool,
+    /// Mixes semantic search into the results, from 0 (keyword only) to 1 (semantic only).
+    pub semantic_ratio: Option<f32>,
 }
 
+/// The [SearchQuery] in the query string, so that searches can be shared and cached by URL.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQueryParams {
+    #[serde(default)]
+    pub query: String,
+    /// The [Filters] encoded as JSON.
+    pub filters: Option<String>,
+    pub sort: Option<SortField>,
+    /// The direction of `sort`, which defaults to descending.
+    pub direction: Option<SortDirection>,
+    pub page: Option<usize>,
+    pub hits_per_page: Option<usize>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub facets: bool,
+    pub semantic_ratio: Option<f32>,
+}
+
+impl TryFrom<SearchQueryParams> for SearchQuery {
+    type Error = AppError;
+
+    fn try_from(params: SearchQueryParams) -> Result<Self, Self::Error> {
+        let filters = params
+            .filters
+            .map(|filters| serde_json::from_str(&filters))
+            .transpose()
+            .map_err(|e| AppError::JsonParseError(e.to_string()))?;
+
+        Ok(SearchQuery {
+            query: params.query,
+            filters,
+            sort: params.sort.map(|field| SortBy {
+                field,
+                direction: params.direction.unwrap_or_default(),
+            }),
+            page: params.page,
+            hits_per_page: params.hits_per_page,
+            offset: params.offset,
+            limit: params.limit,
+            facets: params.facets,
+            semantic_ratio: params.semantic_ratio,
+        })
+    }
+}
+
+/// How long shared caches (ex: a CDN) can serve the results of a GET search.
+const SEARCH_CACHE_CONTROL: HeaderValue = HeaderValue::from_static("public, max-age=60");
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/search",
+    request_body = SearchQuery,
+    responses(
+        (status = 200, body = v1::SearchResponse),
+        (status = 400, description = "Invalid query, filters or pagination", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
 #[tracing::instrument(skip(state))]
 #[axum::debug_handler]
 pub async fn search(
     State(state): State<AppState>,
     Json(query): Json<SearchQuery>,
-) -> Result<Json<SearchResponse>, AppError> {
-    // TODO: add maximum query length
-    let sort = query.sort.unwrap_or_default();
-    let sort = sort.to_meilisearch();
+) -> Result<Json<v1::SearchResponse>, AppError> {
+    Ok(Json(run_search(&state, query).await?))
+}
+
+/// The same as the POST search, with the parameters in the query string.
+#[utoipa::path(
+    get,
+    path = "/api/v1/search",
+    params(SearchQueryParams),
+    responses(
+        (status = 200, body = v1::SearchResponse),
+        (status = 400, description = "Invalid query, filters or pagination", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn search_get(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQueryParams>,
+) -> Result<Response, AppError> {
+    let response = run_search(&state, params.try_into()?).await?;
+
+    Ok((
+        [(header::CACHE_CONTROL, SEARCH_CACHE_CONTROL)],
+        Json(response),
+    )
+        .into_response())
+}
 
-    let page = query.page.unwrap_or(1);
-    let filters = query.filters.as_ref();
+async fn run_search(state: &AppState, query: SearchQuery) -> Result<v1::SearchResponse, AppError> {
+    let pagination =
+        Pagination::from_params(query.page, query.hits_per_page, query.offset, query.limit)
+            .map_err(AppError::InvalidPagination)?;
 
-    // TODO: make this configurable
-    const HITS_PER_PAGE: usize = 20;
+    let mut filters = query.filters.unwrap_or_default();
+    let parsed_query = query::parse_query(&query.query, &mut filters)
+        .map_err(|e| AppError::InvalidQuery(e.to_string()))?;
+    filters.validate().map_err(AppError::InvalidFilters)?;
 
-    let response = state
-        .search(&query.query, filters, &sort, page, HITS_PER_PAGE)
+    let sort = query
+        .sort
+        .unwrap_or_else(|| SortBy::default_for_query(&parsed_query.text));
+    let sort = sort.to_meilisearch();
+
+    if let Some(semantic_ratio) = query.semantic_ratio
+        && !(0.0..=1.0).contains(&semantic_ratio)
+    {
+        return Err(AppError::InvalidQuery(
+            "semanticRatio must be between 0 and 1".to_string(),
+        ));
+    }
+
+    let mut response = state
+        .search(SearchParams {
+            query: &parsed_query.text,
+            filters: Some(&filters),
+            sort,
+            attributes_to_search_on: &parsed_query.attributes_to_search_on,
+            pagination,
+            facets: query.facets,
+            semantic_ratio: query.semantic_ratio,
+        })
         .await?;
 
     debug!("Returning {} results", response.contracts.len());
 
-    Ok(Json(response))
+    response.parsed_query = Some(parsed_query);
+
+    Ok(response.into())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub query: String,
+    /// The [Filters] encoded as JSON.
+    pub filters: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/search/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Every matching contract, streamed in ascending id order, or by relevance with a query",
+            content((String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Invalid query or filters, or a query matching more than 10000 contracts", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn export(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let mut filters: Filters = match &query.filters {
+        Some(filters) => {
+            serde_json::from_str(filters).map_err(|e| AppError::JsonParseError(e.to_string()))?
+        }
+        None => Filters::default(),
+    };
+
+    let parsed_query = query::parse_query(&query.query, &mut filters)
+        .map_err(|e| AppError::InvalidQuery(e.to_string()))?;
+    filters.validate().map_err(AppError::InvalidFilters)?;
+
+    export::export_response(
+        state,
+        parsed_query.text,
+        filters.to_meilisearch(),
+        parsed_query.attributes_to_search_on,
+        query.format,
+    )
+    .await
+}
+
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestQuery {
+    /// The name, alias or NIF of the entity, which can be incomplete.
+    pub q: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/entities/suggest",
+    params(SuggestQuery),
+    responses(
+        (status = 200, body = Vec<v1::EntitySuggestion>),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
 #[tracing::instrument(skip(state))]
 #[axum::debug_handler]
+pub async fn suggest_entities(
+    State(state): State<AppState>,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<Vec<v1::EntitySuggestion>>, AppError> {
+    let entities = state.suggest_entities(&query.q, MAX_SUGGESTIONS).await?;
+
+    Ok(Json(entities.into_iter().map(Into::into).collect()))
+}
+
+const MAX_SIMILAR_CONTRACTS: usize = 10;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/contract/{id}/similar",
+    params(("id" = u64, Path, description = "The Portal BASE id of the contract")),
+    responses(
+        (status = 200, body = Vec<v1::ContractSummary>),
+        (status = 404, description = "Contract not found", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
+#[tracing::instrument(skip(state))]
+#[axum::debug_handler]
+pub async fn similar_contracts(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<Vec<v1::ContractSummary>>, AppError> {
+    let contract = state
+        .get_contract(id)
+        .await?
+        .ok_or(AppError::ContractNotFound(id))?;
+
+    let contracts = state
+        .similar_contracts(&contract, MAX_SIMILAR_CONTRACTS)
+        .await?;
+
+    debug!("Found {} contracts similar to {}", contracts.len(), id);
+
+    Ok(Json(contracts.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/contract/{id}",
+    params(("id" = u64, Path, description = "The Portal BASE id of the contract")),
+    responses(
+        (status = 200, body = v1::Contract),
+        (status = 304, description = "Not modified since the `If-None-Match` or `If-Modified-Since`"),
+        (status = 404, description = "Contract not found", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody)
+    )
+)]
+#[tracing::instrument(skip(state, headers))]
+#[axum::debug_handler]
 pub async fn contract(
     State(state): State<AppState>,
     Path(id): Path<u64>,
-) -> Result<Json<Option<Contract>>, AppError> {
-    let contract = state.get_contract(id).await?;
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // checked before loading the contract, which is the expensive part
+    let updated_at = state
+        .get_contract_updated_at(id)
+        .await?
+        .ok_or(AppError::ContractNotFound(id))?;
+    let validators = Validators::new((id, updated_at), updated_at);
+    if validators.is_fresh(&headers) {
+        return Ok(validators.not_modified());
+    }
+
+    let contract = state
+        .get_contract(id)
+        .await?
+        .ok_or(AppError::ContractNotFound(id))?;
 
     debug!("Contract with ID {} retrieved", id);
 
-    Ok(Json(contract))
+    let contract: v1::Contract = contract.into();
+    Ok(validators.respond(&headers, Json(contract)))
 }
@@ -0,0 +1,214 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use common::{Contract, db::ContractDatabase, searchdb::SearchDatabase};
+use reqwest::Url;
+use scraper::{
+    base_gov::client::BaseGovClient,
+    scraper::throttle::{Priority, Throttler},
+    store::Store,
+};
+use tracing::{error, info};
+
+use crate::error::{AppError, AppResult};
+
+/// Runs the scraper in the background, which the admin can also trigger on demand,
+/// along with the other scraper operations (ex: rebuilding the search index).
+#[derive(Clone)]
+pub struct ScraperHandle {
+    store: Arc<Store>,
+    search_database: SearchDatabase,
+    contract_database: ContractDatabase,
+    base_gov_client_proxy: Option<Url>,
+    /// Shared by everything that scrapes Portal BASE, so that they stay within its budget
+    throttler: Arc<Throttler>,
+    /// Whether the scraper runs periodically in this backend
+    enabled: bool,
+    trigger: Arc<tokio::sync::Notify>,
+    scraping: Arc<AtomicBool>,
+    rebuilding: Arc<AtomicBool>,
+}
+
+/// Resets the flag of a running operation when it ends, even if it panics.
+struct RunningGuard(Arc<AtomicBool>);
+
+impl RunningGuard {
+    /// Sets the flag, or returns None if the operation is already running.
+    fn acquire(flag: &Arc<AtomicBool>) -> Option<Self> {
+        (!flag.swap(true, Ordering::SeqCst)).then(|| Self(flag.clone()))
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl ScraperHandle {
+    pub fn new(
+        store: Arc<Store>,
+        search_database: SearchDatabase,
+        contract_database: ContractDatabase,
+        base_gov_client_proxy: Option<Url>,
+        enabled: bool,
+    ) -> Self {
+        Self {
+            store,
+            search_database,
+            contract_database,
+            base_gov_client_proxy,
+            throttler: Arc::new(scraper::scraper::portal_base_throttler()),
+            enabled,
+            trigger: Default::default(),
+            scraping: Default::default(),
+            rebuilding: Default::default(),
+        }
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    pub fn is_scraping(&self) -> bool {
+        self.scraping.load(Ordering::SeqCst)
+    }
+
+    pub fn is_rebuilding(&self) -> bool {
+        self.rebuilding.load(Ordering::SeqCst)
+    }
+
+    /// Starts a scrape now, or right after the current one if it is running.
+    pub fn trigger_scrape(&self) -> AppResult<()> {
+        if !self.enabled {
+            return Err(AppError::ScraperDisabled);
+        }
+
+        self.trigger.notify_one();
+        Ok(())
+    }
+
+    /// Scrapes every `interval` after the previous scrape, or when triggered.
+    pub async fn run_scrape_task(self, interval: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        loop {
+            if let Some(_guard) = RunningGuard::acquire(&self.scraping) {
+                let base_gov_client = BaseGovClient::new(self.base_gov_client_proxy.clone());
+                let result = scraper::scraper::scrape(
+                    self.store.clone(),
+                    base_gov_client,
+                    self.throttler.clone(),
+                )
+                .await;
+
+                if let Err(e) = result {
+                    error!("Scrape failed: {e:?}");
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = self.trigger.notified() => info!("Scrape triggered"),
+            }
+        }
+    }
+
+    /// Polls Portal BASE for new contracts every `interval`, which is much faster than the scrape
+    /// (that goes from the oldest contracts), so that new contracts are saved within minutes.
+    pub async fn run_poll_task(self, interval: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        let base_gov_client = BaseGovClient::new(self.base_gov_client_proxy.clone());
+        loop {
+            let result = scraper::scraper::poll_new_contracts(
+                &self.store,
+                &base_gov_client,
+                &self.throttler,
+            )
+            .await;
+
+            if let Err(e) = result {
+                error!("Failed to poll new contracts: {e:?}");
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Fetches a batch of the open contracts again every `interval`, to save the fields that
+    /// are only filled in when they are closed.
+    pub async fn run_refresh_task(self, interval: Duration) {
+        if !self.enabled {
+            return;
+        }
+
+        let base_gov_client = BaseGovClient::new(self.base_gov_client_proxy.clone());
+        loop {
+            let result = scraper::refresh::refresh_open_contracts(
+                &self.store,
+                &base_gov_client,
+                &self.throttler,
+                scraper::refresh::REFRESH_BATCH_SIZE,
+            )
+            .await;
+
+            if let Err(e) = result {
+                error!("Failed to refresh open contracts: {e:?}");
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Rebuilds the search index in the background, swapping it when it is complete.
+    pub fn rebuild_search_index(&self) -> AppResult<()> {
+        let guard = RunningGuard::acquire(&self.rebuilding)
+            .ok_or(AppError::AlreadyRunning("search index rebuild"))?;
+
+        let handle = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let result = scraper::search::rebuild::rebuild_search_index(
+                &handle.contract_database,
+                &handle.search_database,
+            )
+            .await;
+
+            match result {
+                Ok(()) => info!("Rebuilt search index"),
+                Err(e) => error!("Failed to rebuild search index: {e:?}"),
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Fetches the contract from Portal BASE again, replacing the saved one.
+    pub async fn refetch_contract(&self, id: u64) -> AppResult<Contract> {
+        let base_gov_client = BaseGovClient::new(self.base_gov_client_proxy.clone());
+        let response = {
+            let _permit = self.throttler.throttle(Priority::High).await;
+            base_gov_client.get_contract_details(id).await
+        };
+
+        let contract: Contract = response
+            .with_context(|| format!("Failed to fetch contract {id} from Portal BASE"))?
+            .into();
+
+        self.store.save_contract(contract.clone()).await?;
+        self.store.finish_batch().await?;
+
+        Ok(contract)
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    num::NonZero,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use common::db::ContractDatabase;
+use itertools::Itertools;
+use lru::LruCache;
+use tracing::{debug, error, warn};
+
+use crate::{
+    pagination::Pagination,
+    state::{AppState, SearchParams, SearchResponse},
+};
+
+const CAPACITY: NonZero<usize> = NonZero::new(1000).unwrap();
+/// How long the results are cached, in case a change to the contracts isn't notified.
+const TTL: Duration = Duration::from_secs(300);
+/// How long to wait after contracts are saved before dropping the cached results,
+/// since they are indexed asynchronously by Meilisearch. It is restarted by the contracts
+/// saved meanwhile, up to [MAX_INVALIDATION_DELAY].
+const INDEXING_DELAY: Duration = Duration::from_secs(5);
+/// How long the cached results are kept at most while contracts keep being saved (ex: scraping).
+const MAX_INVALIDATION_DELAY: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Identifies the results of a search, where the query is normalized,
+/// since Meilisearch ignores its case and extra whitespace.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchCacheKey {
+    query: String,
+    /// The filters sent to Meilisearch, which are sorted since their order doesn't matter
+    filters: Vec<String>,
+    sort: Vec<String>,
+    attributes_to_search_on: Vec<String>,
+    pagination: Pagination,
+    facets: bool,
+    /// The bits of the ratio, since floats aren't hashable
+    semantic_ratio: Option<u32>,
+}
+
+impl SearchCacheKey {
+    pub fn new(params: &SearchParams, filters: &[String]) -> Self {
+        Self {
+            query: params.query.split_whitespace().join(" ").to_lowercase(),
+            filters: filters.iter().cloned().sorted().collect(),
+            sort: params.sort.iter().map(ToString::to_string).collect(),
+            attributes_to_search_on: params
+                .attributes_to_search_on
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            pagination: params.pagination,
+            facets: params.facets,
+            semantic_ratio: params.semantic_ratio.map(f32::to_bits),
+        }
+    }
+}
+
+struct CachedSearch {
+    response: SearchResponse,
+    expires_at: Instant,
+}
+
+struct Inner {
+    entries: LruCache<SearchCacheKey, CachedSearch>,
+    /// Incremented whenever the cache is cleared, so that the results of the searches
+    /// that started before aren't inserted
+    generation: u64,
+}
+
+/// Caches the results of the most recent searches, which are dropped when contracts are saved.
+#[derive(Clone)]
+pub struct SearchCache {
+    contract_database: ContractDatabase,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SearchCache {
+    pub fn new(contract_database: ContractDatabase) -> Self {
+        Self {
+            contract_database,
+            inner: Arc::new(Mutex::new(Inner {
+                entries: LruCache::new(CAPACITY),
+                generation: 0,
+            })),
+        }
+    }
+
+    /// The generation of the cache, which must be read before searching, to insert the results.
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    pub fn get(&self, key: &SearchCacheKey) -> Option<SearchResponse> {
+        let cache = &mut self.inner.lock().unwrap().entries;
+
+        let response = match cache.get(key) {
+            Some(cached) if cached.expires_at > Instant::now() => Some(cached.response.clone()),
+            Some(_) => {
+                cache.pop(key);
+                None
+            }
+            None => None,
+        };
+
+        let result = if response.is_some() { "hit" } else { "miss" };
+        metrics::counter!("search_cache_requests_total", "result" => result).increment(1);
+
+        response
+    }
+
+    /// Inserts the results of a search that started at `generation`,
+    /// unless the cache was cleared since, in which case they may be stale.
+    pub fn insert(&self, key: SearchCacheKey, response: SearchResponse, generation: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+
+        inner.entries.put(
+            key,
+            CachedSearch {
+                response,
+                expires_at: Instant::now() + TTL,
+            },
+        );
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.generation += 1;
+        metrics::counter!("search_cache_invalidations_total").increment(1);
+    }
+
+    /// Drops the cached results whenever contracts are saved by any replica,
+    /// until the connection to the database is lost.
+    async fn listen_contracts_changed(&self) -> anyhow::Result<()> {
+        let mut listener = self.contract_database.listen_contracts_changed().await?;
+
+        loop {
+            match listener.try_recv().await? {
+                Some(_) => {
+                    // waits until no more contracts are saved for a while, so that the cache
+                    // isn't cleared on every save while scraping
+                    let deadline = Instant::now() + MAX_INVALIDATION_DELAY;
+                    while let Ok(notification) = tokio::time::timeout(
+                        INDEXING_DELAY.min(deadline.saturating_duration_since(Instant::now())),
+                        listener.try_recv(),
+                    )
+                    .await
+                    {
+                        if notification?.is_none() {
+                            warn!("Lost the connection listening to contract changes");
+                            break;
+                        }
+                    }
+
+                    debug!("Contracts changed, clearing search cache");
+                    self.clear();
+                }
+                None => {
+                    // notifications may have been missed while reconnecting
+                    warn!("Lost the connection listening to contract changes");
+                    self.clear();
+                }
+            }
+        }
+    }
+}
+
+pub async fn run_invalidate_cache_task(state: AppState) {
+    loop {
+        if let Err(e) = state.search_cache().listen_contracts_changed().await {
+            error!("Failed to listen to contract changes: {e:?}");
+        }
+
+        state.search_cache().clear();
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+
+    fn params(query: &str) -> SearchParams<'_> {
+        SearchParams {
+            query,
+            filters: None,
+            sort: &["id:desc"],
+            attributes_to_search_on: &[],
+            pagination: Pagination::Page {
+                page: 1,
+                hits_per_page: 20,
+            },
+            facets: false,
+            semantic_ratio: None,
+        }
+    }
+
+    fn response() -> SearchResponse {
+        SearchResponse {
+            contracts: Vec::new(),
+            total: 0,
+            page: 1,
+            total_pages: 0,
+            elapsed_millis: 0,
+            hits_per_page: 20,
+            offset: None,
+            facets: None,
+            parsed_query: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_results_are_not_inserted() {
+        // never connected
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost")
+            .unwrap();
+        let cache = SearchCache::new(ContractDatabase::new(pool));
+        let key = SearchCacheKey::new(&params("câmara"), &[]);
+
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert(key.clone(), response(), generation);
+        assert!(cache.get(&key).is_none());
+
+        cache.insert(key.clone(), response(), cache.generation());
+        assert!(cache.get(&key).is_some());
+    }
+
+    #[test]
+    fn test_search_cache_key() {
+        let filters = ["a = 1".to_string(), "b = 2".to_string()];
+
+        let key = SearchCacheKey::new(&params(" Câmara  Municipal "), &filters);
+        let reversed = [filters[1].clone(), filters[0].clone()];
+        assert_eq!(
+            key,
+            SearchCacheKey::new(&params("câmara municipal"), &reversed)
+        );
+        assert_ne!(key, SearchCacheKey::new(&params("câmara"), &filters));
+        assert_ne!(key, SearchCacheKey::new(&params("câmara municipal"), &[]));
+    }
+}
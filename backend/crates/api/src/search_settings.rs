@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use common::db::ContractDatabase;
+use serde::{Deserialize, Serialize};
+
+/// The search settings shipped with the backend, used until they are updated.
+const STARTER_SEARCH_SETTINGS: &str = include_str!("../search-settings.json");
+
+const MAX_SYNONYMS: usize = 2000;
+const MAX_STOP_WORDS: usize = 500;
+const MAX_SEPARATOR_TOKENS: usize = 100;
+
+/// The language specific search settings (ex: abbreviations used by public entities),
+/// which can be updated without a redeploy.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSettings {
+    /// Incremented on every update, so that concurrent updates don't overwrite each other.
+    pub version: u32,
+    #[serde(default)]
+    pub synonyms: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub stop_words: Vec<String>,
+    #[serde(default)]
+    pub separator_tokens: Vec<String>,
+}
+
+/// The subset of the meilisearch index settings set by [SearchSettings].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeilisearchSearchSettings<'a> {
+    synonyms: &'a BTreeMap<String, Vec<String>>,
+    stop_words: &'a [String],
+    separator_tokens: &'a [String],
+}
+
+impl SearchSettings {
+    /// Parses the settings saved in the database, or the starter settings if they were never
+    /// updated.
+    pub fn parse(saved: Option<&str>) -> anyhow::Result<Self> {
+        match saved {
+            Some(saved) => serde_json::from_str(saved).context("Failed to parse search settings"),
+            None => serde_json::from_str(STARTER_SEARCH_SETTINGS)
+                .context("Failed to parse starter search settings"),
+        }
+    }
+
+    /// The settings saved in the database, or the starter settings if they were never updated.
+    pub async fn load(contract_database: &ContractDatabase) -> anyhow::Result<Self> {
+        let saved = contract_database
+            .get_search_settings()
+            .await
+            .context("Failed to load search settings")?;
+        Self::parse(saved.as_deref())
+    }
+
+    /// Saves the settings as the next version, if the saved ones are still at their version
+    /// (or the starter settings, until they are first updated). Returns whether they were saved.
+    pub async fn save_next_version(
+        &mut self,
+        contract_database: &ContractDatabase,
+    ) -> anyhow::Result<bool> {
+        let starter_version = Self::parse(None)?.version;
+        let previous_version = self.version;
+        self.version += 1;
+        let json = serde_json::to_string(self).context("Failed to encode search settings")?;
+
+        // the version is checked by the database, so that concurrent updates on different
+        // backends don't overwrite each other
+        contract_database
+            .save_search_settings(previous_version, starter_version, self.version, &json)
+            .await
+            .context("Failed to save search settings")
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.synonyms.len() > MAX_SYNONYMS {
+            return Err(format!("the maximum number of synonyms is {MAX_SYNONYMS}"));
+        }
+        if self.stop_words.len() > MAX_STOP_WORDS {
+            return Err(format!(
+                "the maximum number of stop words is {MAX_STOP_WORDS}"
+            ));
+        }
+        if self.separator_tokens.len() > MAX_SEPARATOR_TOKENS {
+            return Err(format!(
+                "the maximum number of separator tokens is {MAX_SEPARATOR_TOKENS}"
+            ));
+        }
+
+        for (word, synonyms) in &self.synonyms {
+            if word.trim().is_empty() || synonyms.iter().any(|s| s.trim().is_empty()) {
+                return Err("synonyms can't be empty".to_string());
+            }
+            if synonyms.is_empty() {
+                return Err(format!("'{word}' has no synonyms"));
+            }
+        }
+
+        let mut words = self.stop_words.iter().chain(&self.separator_tokens);
+        if words.any(|word| word.trim().is_empty()) {
+            return Err("stop words and separator tokens can't be empty".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn to_meilisearch(&self) -> MeilisearchSearchSettings<'_> {
+        MeilisearchSearchSettings {
+            synonyms: &self.synonyms,
+            stop_words: &self.stop_words,
+            separator_tokens: &self.separator_tokens,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[test]
+    fn test_starter_search_settings() {
+        let settings = SearchSettings::parse(None).unwrap();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.synonyms["cm"][0], "câmara municipal");
+
+        let mut invalid = settings.clone();
+        invalid.synonyms.insert("jf".to_string(), Vec::new());
+        assert!(invalid.validate().is_err());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_first_update(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+
+        let mut settings = SearchSettings::load(&db).await.unwrap();
+        let starter_version = settings.version;
+        settings.stop_words.push("de".to_string());
+        assert!(settings.save_next_version(&db).await.unwrap());
+
+        let saved = SearchSettings::load(&db).await.unwrap();
+        assert_eq!(saved, settings);
+        assert_eq!(saved.version, starter_version + 1);
+
+        // based on the starter settings, which were replaced
+        let mut stale = SearchSettings::parse(None).unwrap();
+        assert!(!stale.save_next_version(&db).await.unwrap());
+    }
+}
@@ -0,0 +1,122 @@
+use common::{Contract, Entity};
+use itertools::Itertools;
+
+use crate::filter::{Filters, ValueFilters};
+
+/// How many times cheaper or more expensive a similar contract can be.
+const PRICE_FACTOR: i64 = 2;
+
+/// The attribute the source contract description is matched against.
+pub const SIMILAR_ATTRIBUTES_TO_SEARCH_ON: &[&str] = &["objectBriefDescription"];
+
+fn nifs(entities: &[Entity]) -> Vec<String> {
+    entities
+        .iter()
+        .map(|entity| entity.nif.clone())
+        .filter(|nif| !nif.is_empty())
+        .unique()
+        .collect()
+}
+
+/// Builds the meilisearch filters for the contracts similar to the given one:
+/// contracts with the same CPVs, a comparable price and not between the same buyer and supplier.
+pub fn similar_filters(contract: &Contract) -> Vec<String> {
+    let price = contract.initial_contractual_price.0 as i64;
+
+    let filters = Filters {
+        min_price: (price > 0).then_some(price / PRICE_FACTOR),
+        max_price: (price > 0).then_some(price * PRICE_FACTOR),
+        values: ValueFilters {
+            cpv: contract
+                .cpvs
+                .iter()
+                .map(|cpv| cpv.code.clone())
+                .unique()
+                .collect_vec()
+                .into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut filters = filters.to_meilisearch();
+    filters.push(format!("id != {}", contract.id));
+
+    let contracting = nifs(&contract.contracting);
+    let contracted = nifs(&contract.contracted);
+    if !contracting.is_empty() && !contracted.is_empty() {
+        filters.push(format!(
+            "NOT (contracting.nif IN [{}] AND contracted.nif IN [{}])",
+            Filters::format_values(&contracting),
+            Filters::format_values(&contracted)
+        ));
+    }
+
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use common::{Cpv, Currency};
+
+    use super::*;
+
+    fn entity(nif: &str) -> Entity {
+        Entity {
+            id: 0,
+            nif: nif.to_string(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_similar_filters() {
+        let contract = Contract {
+            id: 42,
+            contracting_procedure_type: String::new(),
+            publication_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            signing_date: None,
+            ccp: false,
+            object_brief_description: "Aquisição de papel".to_string(),
+            initial_contractual_price: Currency(10000),
+            description: None,
+            contracting: vec![entity("500000000")],
+            contracted: vec![entity("123456789")],
+            cpvs: vec![Cpv {
+                code: "30197630-1".to_string(),
+                designation: "Papel de impressão".to_string(),
+            }],
+            regime: None,
+            contract_status: None,
+            non_written_contract_justification_types: String::new(),
+            contract_types: String::new(),
+            execution_deadline_days: 0,
+            execution_places: Vec::new(),
+            contract_fundamentation_type: String::new(),
+            contestants: Vec::new(),
+            invitees: Vec::new(),
+            documents: Vec::new(),
+            contracting_procedure_url: None,
+            announcement_id: None,
+            direct_award_fundamentation_type: String::new(),
+            observations: None,
+            end_of_contract_type: None,
+            close_date: None,
+            total_effective_price: None,
+            causes_deadline_change: None,
+            causes_price_change: None,
+        };
+
+        assert_eq!(
+            similar_filters(&contract),
+            vec![
+                "initialContractualPrice >= 5000",
+                "initialContractualPrice <= 20000",
+                "(cpvs.code IN ['30197630-1'] OR cpvPrefixes IN ['30197630-1'])",
+                "id != 42",
+                "NOT (contracting.nif IN ['500000000'] AND contracted.nif IN ['123456789'])",
+            ]
+        );
+    }
+}
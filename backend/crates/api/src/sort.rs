@@ -1,12 +1,14 @@
 use serde::Deserialize;
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Default, ToSchema)]
 pub struct SortBy {
     pub field: SortField,
+    #[serde(default)]
     pub direction: SortDirection,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum SortDirection {
     Ascending,
@@ -14,42 +16,71 @@ pub enum SortDirection {
     Descending,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum SortField {
+    /// Sorts by how well the contracts match the query, ignoring the direction.
+    Relevance,
     Id,
     #[default]
     PublicationDate,
     SigningDate,
     Price,
+    EffectivePrice,
+    CloseDate,
+    Contestants,
 }
 
 impl SortField {
-    pub fn to_meilisearch(&self) -> &'static str {
+    const ALL: [SortField; 8] = [
+        Self::Relevance,
+        Self::Id,
+        Self::PublicationDate,
+        Self::SigningDate,
+        Self::Price,
+        Self::EffectivePrice,
+        Self::CloseDate,
+        Self::Contestants,
+    ];
+
+    /// The sortable attribute of the field, if it sorts by one.
+    pub fn to_meilisearch(&self) -> Option<&'static str> {
         match self {
-            SortField::Id => "id",
-            SortField::PublicationDate => "publicationDate",
-            SortField::SigningDate => "signingDate",
-            SortField::Price => "initialContractualPrice",
+            SortField::Relevance => None,
+            SortField::Id => Some("id"),
+            SortField::PublicationDate => Some("publicationDate"),
+            SortField::SigningDate => Some("signingDate"),
+            SortField::Price => Some("initialContractualPrice"),
+            SortField::EffectivePrice => Some("totalEffectivePrice"),
+            SortField::CloseDate => Some("closeDate"),
+            SortField::Contestants => Some("contestantsCount"),
         }
     }
 
     pub fn to_meilisearch_all() -> Vec<&'static str> {
-        vec![
-            Self::Id.to_meilisearch(),
-            Self::PublicationDate.to_meilisearch(),
-            Self::SigningDate.to_meilisearch(),
-            Self::Price.to_meilisearch(),
-        ]
+        Self::ALL.iter().filter_map(Self::to_meilisearch).collect()
     }
 }
 
 impl SortBy {
+    /// Sorts by relevance when there is text to match, otherwise by the default field.
+    pub fn default_for_query(query: &str) -> Self {
+        if query.trim().is_empty() {
+            SortBy::default()
+        } else {
+            SortBy {
+                field: SortField::Relevance,
+                direction: SortDirection::default(),
+            }
+        }
+    }
+
     pub fn to_meilisearch(&self) -> &'static [&'static str] {
         use SortDirection::*;
         use SortField::*;
 
         match (&self.field, &self.direction) {
+            (Relevance, _) => &[],
             (Id, Ascending) => &["id:asc"],
             (Id, Descending) => &["id:desc"],
             (PublicationDate, Ascending) => &["publicationDate:asc", "id:asc"],
@@ -58,6 +89,12 @@ impl SortBy {
             (SigningDate, Descending) => &["signingDate:desc", "id:desc"],
             (Price, Ascending) => &["initialContractualPrice:asc"],
             (Price, Descending) => &["initialContractualPrice:desc"],
+            (EffectivePrice, Ascending) => &["totalEffectivePrice:asc"],
+            (EffectivePrice, Descending) => &["totalEffectivePrice:desc"],
+            (CloseDate, Ascending) => &["closeDate:asc", "id:asc"],
+            (CloseDate, Descending) => &["closeDate:desc", "id:desc"],
+            (Contestants, Ascending) => &["contestantsCount:asc"],
+            (Contestants, Descending) => &["contestantsCount:desc"],
         }
     }
 }
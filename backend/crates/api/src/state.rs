@@ -4,35 +4,84 @@ use std::{
 };
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use common::{
-    Contract, SearchableContract, db::ContractDatabase, searchdb::SearchDatabase,
-    statistics::Statistics,
+    Contract, SearchableContract, db::ContractDatabase, embedder::Embedder,
+    entities::SearchableEntity, searchdb::SearchDatabase, statistics::Statistics,
+};
+use itertools::Itertools;
+use meilisearch_sdk::{
+    request::Method,
+    search::{MatchingStrategies, Selectors},
+    settings::{PaginationSetting, Settings},
+    task_info::TaskInfo,
 };
-use meilisearch_sdk::settings::{PaginationSetting, Settings};
 use serde::Serialize;
+use tracing::warn;
+use utoipa::ToSchema;
 
-use crate::{error::AppResult, filter::Filters, sort::SortField};
+use crate::{
+    api_key::ApiKeys,
+    conditional::Validators,
+    error::{AppError, AppResult},
+    export::ExportPosition,
+    facet::{FacetField, Facets},
+    filter::Filters,
+    pagination::Pagination,
+    query::ParsedQuery,
+    search_cache::{SearchCache, SearchCacheKey},
+    search_settings::SearchSettings,
+    similar,
+    sort::SortField,
+};
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
+const MAX_VALUES_PER_FACET: usize = 100;
+
+#[derive(Debug, Clone)]
 pub struct SearchResponse {
     pub contracts: Vec<SearchedContract>,
+    /// Estimated in the offset/limit pagination mode.
     pub total: usize,
+    /// 0 in the offset/limit pagination mode, as well as `total_pages`.
     pub page: usize,
     pub total_pages: usize,
     pub elapsed_millis: u64,
     pub hits_per_page: usize,
+    /// Only present in the offset/limit pagination mode.
+    pub offset: Option<usize>,
+    pub facets: Option<Facets>,
+    pub parsed_query: Option<ParsedQuery>,
 }
 
+#[derive(Debug)]
+pub struct SearchParams<'a> {
+    pub query: &'a str,
+    pub filters: Option<&'a Filters>,
+    pub sort: &'a [&'a str],
+    /// Restricts the text search to these attributes, or searches all of them if empty.
+    pub attributes_to_search_on: &'a [&'a str],
+    pub pagination: Pagination,
+    /// Whether to compute the facet distributions of the results.
+    pub facets: bool,
+    /// Mixes semantic search into the results, from 0 (keyword only) to 1 (semantic only).
+    pub semantic_ratio: Option<f32>,
+}
+
+/// The faceting settings not supported by [meilisearch_sdk::settings::FacetingSettings].
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
+struct FacetingSettings {
+    max_values_per_facet: usize,
+    sort_facet_values_by: HashMap<&'static str, &'static str>,
+}
+
+#[derive(Debug, Clone)]
 pub struct SearchedContract {
-    #[serde(flatten)]
     pub contract: SearchableContract,
     pub matching_ranges: HashMap<String, Vec<MatchingRange>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MatchingRange {
     pub start: usize,
@@ -51,10 +100,42 @@ impl From<meilisearch_sdk::search::MatchRange> for MatchingRange {
     }
 }
 
+/// The statistics with the validators of their version, for conditional requests.
+#[derive(Debug, Clone)]
+pub struct StatisticsSnapshot {
+    pub statistics: Statistics,
+    pub validators: Validators,
+    /// None until the statistics are loaded for the first time
+    pub reloaded_at: Option<DateTime<Utc>>,
+}
+
+impl StatisticsSnapshot {
+    /// Keeps the last modified date of the previous snapshot if the statistics didn't change.
+    fn new(statistics: Statistics, previous: Option<&StatisticsSnapshot>) -> Self {
+        let mut validators = Validators::new(&statistics, Utc::now());
+
+        if let Some(previous) = previous
+            && previous.validators.same_version(&validators)
+        {
+            validators = previous.validators.clone();
+        }
+
+        Self {
+            statistics,
+            validators,
+            reloaded_at: previous.map(|_| Utc::now()),
+        }
+    }
+}
+
 pub struct AppState {
     search_database: SearchDatabase,
     contract_database: ContractDatabase,
-    statistics: Arc<RwLock<Statistics>>,
+    statistics: Arc<RwLock<StatisticsSnapshot>>,
+    /// The embedder used for semantic search, which is disabled if not configured.
+    embedder: Option<Embedder>,
+    api_keys: ApiKeys,
+    search_cache: SearchCache,
 }
 
 impl Clone for AppState {
@@ -63,20 +144,41 @@ impl Clone for AppState {
             search_database: self.search_database.clone(),
             contract_database: self.contract_database.clone(),
             statistics: Arc::clone(&self.statistics),
+            embedder: self.embedder.clone(),
+            api_keys: self.api_keys.clone(),
+            search_cache: self.search_cache.clone(),
         }
     }
 }
 
 impl AppState {
-    pub fn new(search_database: SearchDatabase, contract_database: ContractDatabase) -> Self {
+    pub fn new(
+        search_database: SearchDatabase,
+        contract_database: ContractDatabase,
+        embedder: Option<Embedder>,
+    ) -> Self {
         Self {
             search_database,
+            api_keys: ApiKeys::new(contract_database.clone()),
+            search_cache: SearchCache::new(contract_database.clone()),
             contract_database,
-            statistics: Default::default(),
+            statistics: Arc::new(RwLock::new(StatisticsSnapshot::new(
+                Statistics::default(),
+                None,
+            ))),
+            embedder,
         }
     }
 
-    pub fn get_statistics(&self) -> Statistics {
+    pub fn api_keys(&self) -> &ApiKeys {
+        &self.api_keys
+    }
+
+    pub fn search_cache(&self) -> &SearchCache {
+        &self.search_cache
+    }
+
+    pub fn get_statistics(&self) -> StatisticsSnapshot {
         self.statistics.read().unwrap().clone()
     }
 
@@ -93,23 +195,53 @@ impl AppState {
             .context("Failed to compute statistics")?;
 
         if let Ok(mut statistics) = self.statistics.write() {
-            *statistics = new_statistics;
+            *statistics = StatisticsSnapshot::new(new_statistics, Some(&statistics));
         }
 
         Ok(())
     }
 
-    pub async fn prepare_settings(&self) -> anyhow::Result<()> {
+    /// Sets the embedder in meilisearch, or disables semantic search if it can't be reached
+    /// (ex: the embedding service is down), in which case its embeddings are kept.
+    async fn prepare_embedder(&mut self) -> anyhow::Result<()> {
+        // an embedder that is no longer configured is removed, so that meilisearch stops calling it
+        let embedder_settings = match &self.embedder {
+            Some(embedder) => match embedder.dimensions().await {
+                Ok(dimensions) => Some(embedder.to_meilisearch(dimensions)),
+                Err(e) => {
+                    warn!("Failed to check the embedder, semantic search is disabled: {e:?}");
+                    self.embedder = None;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        self.search_database
+            .set_embedder(embedder_settings)
+            .await
+            .context("Failed to set embedder settings")?;
+
+        Ok(())
+    }
+
+    pub async fn prepare_settings(&mut self) -> anyhow::Result<()> {
         let contracts_index = self.search_database.index();
 
+        let filterable_attributes = Filters::fields_to_meilisearch_all()
+            .into_iter()
+            .chain(FacetField::to_meilisearch_all())
+            .unique()
+            .collect_vec();
+
         let settings = Settings::new()
             .with_sortable_attributes(SortField::to_meilisearch_all())
-            .with_filterable_attributes(Filters::fields_to_meilisearch_all())
+            .with_filterable_attributes(filterable_attributes)
             .with_pagination(PaginationSetting {
                 // this is not recommended by meilisearch docs but it is having good performance for now and it is essential for UX
                 max_total_hits: 3000000,
             })
-            .with_ranking_rules(&[
+            .with_ranking_rules([
                 "words",
                 "typo",
                 "proximity",
@@ -123,33 +255,181 @@ impl AppState {
             .await
             .context("Failed to set settings")?;
 
+        // the facets with most values (ex: entities) are truncated to the most frequent ones
+        let faceting = FacetingSettings {
+            max_values_per_facet: MAX_VALUES_PER_FACET,
+            sort_facet_values_by: HashMap::from([("*", "count")]),
+        };
+
+        self.search_database
+            .raw_request::<_, TaskInfo>(
+                &format!("indexes/{}/settings/faceting", contracts_index.uid),
+                Method::Patch {
+                    query: (),
+                    body: faceting,
+                },
+                202,
+            )
+            .await
+            .context("Failed to set faceting settings")?;
+
+        self.prepare_embedder().await?;
+
+        let search_settings = self
+            .get_search_settings()
+            .await
+            .context("Failed to load search settings")?;
+        self.apply_search_settings(&search_settings)
+            .await
+            .context("Failed to set search settings")?;
+
+        let entities_settings = Settings::new()
+            .with_searchable_attributes(["name", "aliases", "nif"])
+            .with_sortable_attributes(["contractCount", "totalValue"])
+            .with_ranking_rules([
+                "words",
+                "typo",
+                "proximity",
+                "sort",
+                "attribute",
+                "exactness",
+            ]);
+
+        self.search_database
+            .entities_index()
+            .set_settings(&entities_settings)
+            .await
+            .context("Failed to set entities settings")?;
+
         Ok(())
     }
 
+    async fn apply_search_settings(&self, settings: &SearchSettings) -> AppResult<TaskInfo> {
+        let task = self
+            .search_database
+            .raw_request(
+                &format!("indexes/{}/settings", self.search_database.index().uid),
+                Method::Patch {
+                    query: (),
+                    body: settings.to_meilisearch(),
+                },
+                202,
+            )
+            .await?;
+
+        Ok(task)
+    }
+
+    /// The search settings saved in the database, which are shared by every backend.
+    pub async fn get_search_settings(&self) -> AppResult<SearchSettings> {
+        Ok(SearchSettings::load(&self.contract_database).await?)
+    }
+
+    /// Saves the new search settings and applies them, if they are based on the current version.
+    /// Meilisearch reindexes the contracts in the background when the settings change.
+    pub async fn update_search_settings(
+        &self,
+        mut settings: SearchSettings,
+    ) -> AppResult<SearchSettings> {
+        settings
+            .validate()
+            .map_err(AppError::InvalidSearchSettings)?;
+
+        if !settings.save_next_version(&self.contract_database).await? {
+            let current = self.get_search_settings().await?;
+            return Err(AppError::SearchSettingsConflict(current.version));
+        }
+
+        self.apply_search_settings(&settings).await?;
+
+        // the cached results were computed with the previous settings, including on other backends
+        self.search_cache.clear();
+        if let Err(e) = self.contract_database.notify_contracts_changed().await {
+            warn!("Failed to notify that the search settings changed: {e:?}");
+        }
+
+        Ok(settings)
+    }
+
     #[tracing::instrument(skip(self))]
-    pub async fn search(
+    pub async fn search(&self, params: SearchParams<'_>) -> AppResult<SearchResponse> {
+        if params.semantic_ratio.is_some() && self.embedder.is_none() {
+            return Err(AppError::SemanticSearchDisabled);
+        }
+
+        let filters = params
+            .filters
+            .map(Filters::to_meilisearch)
+            .unwrap_or_default();
+        let cache_key = SearchCacheKey::new(&params, &filters);
+        if let Some(response) = self.search_cache.get(&cache_key) {
+            return Ok(response);
+        }
+
+        let generation = self.search_cache.generation();
+        let response = self.search_uncached(params, &filters).await?;
+        self.search_cache
+            .insert(cache_key, response.clone(), generation);
+
+        Ok(response)
+    }
+
+    async fn search_uncached(
         &self,
-        query: &str,
-        filters: Option<&Filters>,
-        sort: &[&str],
-        page: usize,
-        hits_per_page: usize,
+        params: SearchParams<'_>,
+        filters: &[String],
     ) -> AppResult<SearchResponse> {
-        let filters = filters.map(Filters::to_meilisearch).unwrap_or_default();
+        let SearchParams {
+            query,
+            filters: _,
+            sort,
+            attributes_to_search_on,
+            pagination,
+            facets,
+            semantic_ratio,
+        } = params;
+
         let filters_ref = filters.iter().map(String::as_str).collect();
+        let facet_attributes = FacetField::to_meilisearch_query();
 
-        let results = self
-            .search_database
-            .index()
-            .search()
+        let index = self.search_database.index();
+        let mut search = index.search();
+        search
             .with_query(query)
             .with_array_filter(filters_ref)
             .with_sort(sort)
-            .with_page(page)
-            .with_hits_per_page(hits_per_page)
-            .with_show_matches_position(true)
-            .execute::<SearchableContract>()
-            .await?;
+            .with_show_matches_position(true);
+
+        match pagination {
+            Pagination::Page {
+                page,
+                hits_per_page,
+            } => search.with_page(page).with_hits_per_page(hits_per_page),
+            Pagination::OffsetLimit { offset, limit } => {
+                search.with_offset(offset).with_limit(limit)
+            }
+        };
+
+        if !attributes_to_search_on.is_empty() {
+            search.with_attributes_to_search_on(attributes_to_search_on);
+        }
+
+        if facets {
+            search.with_facets(Selectors::Some(&facet_attributes));
+        }
+
+        let results = match semantic_ratio {
+            Some(semantic_ratio) => {
+                self.search_database
+                    .hybrid_search::<SearchableContract>(&search, semantic_ratio)
+                    .await?
+            }
+            None => search.execute::<SearchableContract>().await?,
+        };
+
+        let facets = results.facet_distribution.map(|distribution| {
+            Facets::from_meilisearch(distribution, &results.facet_stats.unwrap_or_default())
+        });
 
         let contracts = results
             .hits
@@ -168,13 +448,173 @@ impl AppState {
         Ok(SearchResponse {
             contracts,
             page: results.page.unwrap_or(0),
-            total: results.total_hits.unwrap_or(0),
+            total: results
+                .total_hits
+                .or(results.estimated_total_hits)
+                .unwrap_or(0),
             total_pages: results.total_pages.unwrap_or(0),
             elapsed_millis: results.processing_time_ms as u64,
-            hits_per_page,
+            hits_per_page: pagination.hits_per_page(),
+            offset: results.offset,
+            facets,
+            parsed_query: None,
         })
     }
 
+    /// Counts the contracts matching an export with a query, which are paged by offset.
+    #[tracing::instrument(skip(self))]
+    pub async fn count_for_export(
+        &self,
+        query: &str,
+        filters: &[String],
+        attributes_to_search_on: &[&str],
+    ) -> AppResult<usize> {
+        let filters_ref = filters.iter().map(String::as_str).collect();
+
+        let index = self.search_database.index();
+        let mut search = index.search();
+        search
+            .with_query(query)
+            .with_array_filter(filters_ref)
+            .with_page(1)
+            .with_hits_per_page(0);
+
+        if !attributes_to_search_on.is_empty() {
+            search.with_attributes_to_search_on(attributes_to_search_on);
+        }
+
+        let results = search.execute::<SearchableContract>().await?;
+
+        Ok(results.total_hits.unwrap_or_default())
+    }
+
+    /// Searches the next batch of an export from `position`, sorted by id.
+    #[tracing::instrument(skip(self))]
+    pub async fn search_for_export(
+        &self,
+        query: &str,
+        filters: &[String],
+        attributes_to_search_on: &[&str],
+        position: ExportPosition,
+        limit: usize,
+    ) -> AppResult<Vec<SearchableContract>> {
+        let after_id_filter = match position {
+            ExportPosition::AfterId(Some(id)) => Some(format!("id > {id}")),
+            ExportPosition::AfterId(None) | ExportPosition::Offset(_) => None,
+        };
+        let filters_ref = filters
+            .iter()
+            .chain(after_id_filter.as_ref())
+            .map(String::as_str)
+            .collect();
+
+        let index = self.search_database.index();
+        let mut search = index.search();
+        search
+            .with_query(query)
+            .with_array_filter(filters_ref)
+            .with_sort(&["id:asc"])
+            .with_limit(limit);
+
+        if let ExportPosition::Offset(offset) = position {
+            search.with_offset(offset);
+        }
+
+        if !attributes_to_search_on.is_empty() {
+            search.with_attributes_to_search_on(attributes_to_search_on);
+        }
+
+        let results = search.execute::<SearchableContract>().await?;
+
+        Ok(results.hits.into_iter().map(|hit| hit.result).collect())
+    }
+
+    /// Suggests entities matching the query by name, alias or NIF,
+    /// where the entities with most contracts come first.
+    #[tracing::instrument(skip(self))]
+    pub async fn suggest_entities(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> AppResult<Vec<SearchableEntity>> {
+        let results = self
+            .search_database
+            .entities_index()
+            .search()
+            .with_query(query)
+            .with_sort(&["contractCount:desc"])
+            .with_limit(limit)
+            .execute::<SearchableEntity>()
+            .await?;
+
+        Ok(results.hits.into_iter().map(|hit| hit.result).collect())
+    }
+
+    /// Searches contracts similar to the given one, by relevance to its object description,
+    /// restricted to its CPVs and a comparable price (see [similar::similar_filters]).
+    #[tracing::instrument(skip(self, contract), fields(id = contract.id))]
+    pub async fn similar_contracts(
+        &self,
+        contract: &Contract,
+        limit: usize,
+    ) -> AppResult<Vec<SearchableContract>> {
+        let filters = similar::similar_filters(contract);
+        let filters_ref = filters.iter().map(String::as_str).collect();
+
+        let results = self
+            .search_database
+            .index()
+            .search()
+            .with_query(&contract.object_brief_description)
+            .with_array_filter(filters_ref)
+            .with_attributes_to_search_on(similar::SIMILAR_ATTRIBUTES_TO_SEARCH_ON)
+            // descriptions are long, so the most common words are dropped until there are results
+            .with_matching_strategy(MatchingStrategies::FREQUENCY)
+            .with_limit(limit)
+            .execute::<SearchableContract>()
+            .await?;
+
+        Ok(results.hits.into_iter().map(|hit| hit.result).collect())
+    }
+
+    /// Checks that Postgres is reachable.
+    pub async fn ping_contract_database(&self) -> AppResult<()> {
+        self.contract_database.ping().await.map_err(Into::into)
+    }
+
+    /// The number of documents in the contracts index, which fails if the index doesn't exist.
+    pub async fn count_indexed_contracts(&self) -> AppResult<usize> {
+        let stats = self.search_database.index().get_stats().await?;
+        Ok(stats.number_of_documents)
+    }
+
+    pub async fn get_last_successful_scrape(&self) -> AppResult<Option<DateTime<Utc>>> {
+        self.contract_database
+            .get_last_successful_scrape()
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn record_admin_action(
+        &self,
+        action: &str,
+        path: &str,
+        status: u16,
+        client_ip: Option<&str>,
+    ) -> AppResult<()> {
+        self.contract_database
+            .record_admin_action(action, path, status, client_ip)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn get_contract_updated_at(&self, id: u64) -> AppResult<Option<DateTime<Utc>>> {
+        self.contract_database
+            .get_contract_updated_at(id)
+            .await
+            .map_err(Into::into)
+    }
+
     pub async fn get_contract(&self, id: u64) -> AppResult<Option<Contract>> {
         self.contract_database
             .get_contract(id)
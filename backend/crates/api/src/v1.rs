@@ -0,0 +1,322 @@
+//! The types of the responses of the v1 API.
+//!
+//! They are decoupled from the structs saved in the databases, so that the storage can change
+//! without breaking the clients. Changing these types in a breaking way requires a new version.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    facet::Facets,
+    query::ParsedQuery,
+    state::{self, MatchingRange},
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Contract {
+    /// The Portal BASE id of the contract
+    pub id: u64,
+    pub contracting_procedure_type: String,
+    pub publication_date: NaiveDate,
+    pub signing_date: Option<NaiveDate>,
+    /// Whether the contract is covered by the Public Contracts Code
+    pub ccp: bool,
+    pub object_brief_description: String,
+    /// In cents
+    pub initial_contractual_price: i64,
+    pub description: Option<String>,
+    pub contracting: Vec<Entity>,
+    pub contracted: Vec<Entity>,
+    pub cpvs: Vec<Cpv>,
+    pub regime: Option<String>,
+    pub contract_status: Option<String>,
+    pub non_written_contract_justification_types: String,
+    pub contract_types: String,
+    pub execution_deadline_days: usize,
+    pub execution_places: Vec<String>,
+    pub contract_fundamentation_type: String,
+    pub contestants: Vec<Entity>,
+    pub invitees: Vec<Entity>,
+    pub documents: Vec<Document>,
+    pub contracting_procedure_url: Option<String>,
+    pub announcement_id: Option<usize>,
+    pub direct_award_fundamentation_type: String,
+    pub observations: Option<String>,
+    pub end_of_contract_type: Option<String>,
+    pub close_date: Option<NaiveDate>,
+    /// In cents
+    pub total_effective_price: Option<i64>,
+    pub causes_deadline_change: Option<String>,
+    pub causes_price_change: Option<String>,
+}
+
+/// The fields of a contract returned in the lists of contracts (ex: search results).
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractSummary {
+    /// The Portal BASE id of the contract
+    pub id: u64,
+    pub contracting_procedure_type: String,
+    pub publication_date: NaiveDate,
+    pub signing_date: Option<NaiveDate>,
+    /// Whether the contract is covered by the Public Contracts Code
+    pub ccp: bool,
+    pub object_brief_description: String,
+    pub description: Option<String>,
+    /// In cents
+    pub initial_contractual_price: i64,
+    pub contracting: Vec<Entity>,
+    pub contracted: Vec<Entity>,
+    pub cpvs: Vec<Cpv>,
+    pub regime: Option<String>,
+    pub contract_types: String,
+    pub execution_places: Vec<String>,
+    pub contract_fundamentation_type: String,
+    pub contestants: Vec<Entity>,
+    pub invitees: Vec<Entity>,
+    pub documents: Vec<Document>,
+    pub contracting_procedure_url: Option<String>,
+    pub announcement_id: Option<usize>,
+    pub close_date: Option<NaiveDate>,
+    /// In cents
+    pub total_effective_price: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Entity {
+    /// The Portal BASE id of the entity
+    pub id: u64,
+    pub nif: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Cpv {
+    pub code: String,
+    pub designation: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Document {
+    pub id: u64,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    pub contracts: Vec<SearchedContract>,
+    /// Estimated in the offset/limit pagination mode.
+    pub total: usize,
+    /// 0 in the offset/limit pagination mode, as well as `total_pages`.
+    pub page: usize,
+    pub total_pages: usize,
+    pub elapsed_millis: u64,
+    pub hits_per_page: usize,
+    /// Only present in the offset/limit pagination mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Facets>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parsed_query: Option<ParsedQuery>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchedContract {
+    #[serde(flatten)]
+    pub contract: ContractSummary,
+    /// The ranges of the fields that matched the query, by field name.
+    pub matching_ranges: HashMap<String, Vec<MatchingRange>>,
+}
+
+/// An entity (contracting or contracted) suggested for a partial name or NIF.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EntitySuggestion {
+    /// The Portal BASE id of the entity
+    pub id: u64,
+    pub nif: String,
+    /// The most used name of the entity across its contracts
+    pub name: String,
+    /// Every name the entity has been registered with
+    pub aliases: Vec<String>,
+    pub contracting_count: u64,
+    pub contracted_count: u64,
+    pub contract_count: u64,
+    /// The sum (in cents) of the initial contractual prices of the entity's contracts
+    pub total_value: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Statistics {
+    /// In cents
+    pub total_spent_last_365_days: i64,
+    pub contracts_last_365_days: i64,
+    /// In cents
+    pub total_spent_last_30_days: i64,
+    pub contracts_last_30_days: i64,
+    /// In cents
+    pub total_spent_last_7_days: i64,
+    pub contracts_last_7_days: i64,
+}
+
+fn cents(common::Currency(cents): common::Currency) -> i64 {
+    cents as i64
+}
+
+fn entities(entities: Vec<common::Entity>) -> Vec<Entity> {
+    entities.into_iter().map(Entity::from).collect()
+}
+
+impl From<common::Contract> for Contract {
+    fn from(contract: common::Contract) -> Self {
+        Contract {
+            id: contract.id,
+            contracting_procedure_type: contract.contracting_procedure_type,
+            publication_date: contract.publication_date,
+            signing_date: contract.signing_date,
+            ccp: contract.ccp,
+            object_brief_description: contract.object_brief_description,
+            initial_contractual_price: cents(contract.initial_contractual_price),
+            description: contract.description,
+            contracting: entities(contract.contracting),
+            contracted: entities(contract.contracted),
+            cpvs: contract.cpvs.into_iter().map(Cpv::from).collect(),
+            regime: contract.regime,
+            contract_status: contract.contract_status,
+            non_written_contract_justification_types: contract
+                .non_written_contract_justification_types,
+            contract_types: contract.contract_types,
+            execution_deadline_days: contract.execution_deadline_days,
+            execution_places: contract.execution_places,
+            contract_fundamentation_type: contract.contract_fundamentation_type,
+            contestants: entities(contract.contestants),
+            invitees: entities(contract.invitees),
+            documents: contract.documents.into_iter().map(Document::from).collect(),
+            contracting_procedure_url: contract.contracting_procedure_url,
+            announcement_id: contract.announcement_id,
+            direct_award_fundamentation_type: contract.direct_award_fundamentation_type,
+            observations: contract.observations,
+            end_of_contract_type: contract.end_of_contract_type,
+            close_date: contract.close_date,
+            total_effective_price: contract.total_effective_price.map(cents),
+            causes_deadline_change: contract.causes_deadline_change,
+            causes_price_change: contract.causes_price_change,
+        }
+    }
+}
+
+impl From<common::SearchableContract> for ContractSummary {
+    fn from(contract: common::SearchableContract) -> Self {
+        ContractSummary {
+            id: contract.id,
+            contracting_procedure_type: contract.contracting_procedure_type,
+            publication_date: contract.publication_date,
+            signing_date: contract.signing_date,
+            ccp: contract.ccp,
+            object_brief_description: contract.object_brief_description,
+            description: contract.description,
+            initial_contractual_price: cents(contract.initial_contractual_price),
+            contracting: entities(contract.contracting),
+            contracted: entities(contract.contracted),
+            cpvs: contract.cpvs.into_iter().map(Cpv::from).collect(),
+            regime: contract.regime,
+            contract_types: contract.contract_types,
+            execution_places: contract.execution_places,
+            contract_fundamentation_type: contract.contract_fundamentation_type,
+            contestants: entities(contract.contestants),
+            invitees: entities(contract.invitees),
+            documents: contract.documents.into_iter().map(Document::from).collect(),
+            contracting_procedure_url: contract.contracting_procedure_url,
+            announcement_id: contract.announcement_id,
+            close_date: contract.close_date,
+            total_effective_price: contract.total_effective_price.map(cents),
+        }
+    }
+}
+
+impl From<common::Entity> for Entity {
+    fn from(entity: common::Entity) -> Self {
+        Entity {
+            id: entity.id,
+            nif: entity.nif,
+            description: entity.description,
+        }
+    }
+}
+
+impl From<common::Cpv> for Cpv {
+    fn from(cpv: common::Cpv) -> Self {
+        Cpv {
+            code: cpv.code,
+            designation: cpv.designation,
+        }
+    }
+}
+
+impl From<common::Document> for Document {
+    fn from(document: common::Document) -> Self {
+        Document {
+            id: document.id,
+            description: document.description,
+        }
+    }
+}
+
+impl From<state::SearchResponse> for SearchResponse {
+    fn from(response: state::SearchResponse) -> Self {
+        SearchResponse {
+            contracts: response
+                .contracts
+                .into_iter()
+                .map(|hit| SearchedContract {
+                    contract: hit.contract.into(),
+                    matching_ranges: hit.matching_ranges,
+                })
+                .collect(),
+            total: response.total,
+            page: response.page,
+            total_pages: response.total_pages,
+            elapsed_millis: response.elapsed_millis,
+            hits_per_page: response.hits_per_page,
+            offset: response.offset,
+            facets: response.facets,
+            parsed_query: response.parsed_query,
+        }
+    }
+}
+
+impl From<common::entities::SearchableEntity> for EntitySuggestion {
+    fn from(entity: common::entities::SearchableEntity) -> Self {
+        EntitySuggestion {
+            id: entity.id,
+            nif: entity.nif,
+            name: entity.name,
+            aliases: entity.aliases,
+            contracting_count: entity.contracting_count,
+            contracted_count: entity.contracted_count,
+            contract_count: entity.contract_count,
+            total_value: entity.total_value,
+        }
+    }
+}
+
+impl From<common::statistics::Statistics> for Statistics {
+    fn from(statistics: common::statistics::Statistics) -> Self {
+        Statistics {
+            total_spent_last_365_days: statistics.total_spent_last_365_days,
+            contracts_last_365_days: statistics.contracts_last_365_days,
+            total_spent_last_30_days: statistics.total_spent_last_30_days,
+            contracts_last_30_days: statistics.contracts_last_30_days,
+            total_spent_last_7_days: statistics.total_spent_last_7_days,
+            contracts_last_7_days: statistics.contracts_last_7_days,
+        }
+    }
+}
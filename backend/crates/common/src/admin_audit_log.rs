@@ -0,0 +1,60 @@
+use crate::db::ContractDatabase;
+
+impl ContractDatabase {
+    /// Records a request to the admin routes, with the status it was answered with.
+    pub async fn record_admin_action(
+        &self,
+        action: &str,
+        path: &str,
+        status: u16,
+        client_ip: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO admin_audit_log (action, path, status, client_ip) VALUES ($1, $2, $3, $4)",
+            action,
+            path,
+            status as i16,
+            client_ip
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_record_admin_action(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool.clone());
+        db.record_admin_action(
+            "POST /api/admin/contracts/{id}/refetch",
+            "/api/admin/contracts/1/refetch",
+            200,
+            Some("127.0.0.1"),
+        )
+        .await
+        .unwrap();
+        db.record_admin_action("POST /api/admin/scrape", "/api/admin/scrape", 401, None)
+            .await
+            .unwrap();
+
+        let statuses: Vec<(String, i16)> =
+            sqlx::query_as("SELECT action, status FROM admin_audit_log ORDER BY id")
+                .fetch_all(&pg_pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            statuses,
+            [
+                ("POST /api/admin/contracts/{id}/refetch".to_string(), 200),
+                ("POST /api/admin/scrape".to_string(), 401)
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::db::ContractDatabase;
+
+const API_KEY_PREFIX: &str = "cp_";
+
+/// The quota tier of an API key, which multiplies the rate limits of anonymous clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum ApiKeyTier {
+    Standard,
+    /// For researchers and journalists that need to go through large amounts of contracts
+    Research,
+    /// Not rate limited, for trusted partners
+    Unlimited,
+}
+
+impl ApiKeyTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyTier::Standard => "standard",
+            ApiKeyTier::Research => "research",
+            ApiKeyTier::Unlimited => "unlimited",
+        }
+    }
+}
+
+impl fmt::Display for ApiKeyTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiKeyTier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(ApiKeyTier::Standard),
+            "research" => Ok(ApiKeyTier::Research),
+            "unlimited" => Ok(ApiKeyTier::Unlimited),
+            _ => Err(anyhow::anyhow!("Unknown API key tier {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: i64,
+    /// Who the key was issued to
+    pub name: String,
+    pub tier: ApiKeyTier,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub request_count: i64,
+    pub rate_limited_count: i64,
+}
+
+struct ApiKeyRow {
+    id: i64,
+    name: String,
+    tier: String,
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    request_count: i64,
+    rate_limited_count: i64,
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, Self::Error> {
+        Ok(ApiKey {
+            id: row.id,
+            name: row.name,
+            tier: row.tier.parse()?,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at,
+            last_used_at: row.last_used_at,
+            request_count: row.request_count,
+            rate_limited_count: row.rate_limited_count,
+        })
+    }
+}
+
+/// Generates a new random API key (ex: "cp_0f8fad5bd9cb469fa16570867728950e").
+fn generate_api_key() -> String {
+    format!("{API_KEY_PREFIX}{}", Uuid::new_v4().simple())
+}
+
+/// The keys are random, so a fast hash is enough to not store them in plain text.
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+impl ContractDatabase {
+    /// Issues a new API key, returning it along with the key, which can't be recovered later.
+    pub async fn issue_api_key(
+        &self,
+        name: &str,
+        tier: ApiKeyTier,
+    ) -> anyhow::Result<(ApiKey, String)> {
+        let key = generate_api_key();
+
+        let row = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+            INSERT INTO api_keys (name, key_hash, tier)
+            VALUES ($1, $2, $3)
+            RETURNING id, name, tier, created_at, revoked_at, last_used_at,
+                request_count, rate_limited_count
+            "#,
+            name,
+            hash_api_key(&key),
+            tier.as_str()
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((row.try_into()?, key))
+    }
+
+    /// Revokes an API key, returning false if it doesn't exist or was already revoked.
+    pub async fn revoke_api_key(&self, id: i64) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Finds the API key that is not revoked with the given key.
+    pub async fn find_api_key(&self, key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let row = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+            SELECT id, name, tier, created_at, revoked_at, last_used_at,
+                request_count, rate_limited_count
+            FROM api_keys
+            WHERE key_hash = $1 AND revoked_at IS NULL
+            "#,
+            hash_api_key(key)
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    pub async fn list_api_keys(&self) -> anyhow::Result<Vec<ApiKey>> {
+        let rows = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+            SELECT id, name, tier, created_at, revoked_at, last_used_at,
+                request_count, rate_limited_count
+            FROM api_keys
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(ApiKey::try_from).collect()
+    }
+
+    /// Adds to the usage counters of an API key, which are accumulated in memory between calls.
+    pub async fn record_api_key_usage(
+        &self,
+        id: i64,
+        requests: u64,
+        rate_limited: u64,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET request_count = request_count + $2,
+                rate_limited_count = rate_limited_count + $3,
+                last_used_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            requests as i64,
+            rate_limited as i64
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_api_key_lifecycle(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+
+        let (api_key, key) = db
+            .issue_api_key("University of Porto", ApiKeyTier::Research)
+            .await
+            .unwrap();
+        assert!(key.starts_with(API_KEY_PREFIX));
+
+        let found = db.find_api_key(&key).await.unwrap().unwrap();
+        assert_eq!(found.id, api_key.id);
+        assert_eq!(found.tier, ApiKeyTier::Research);
+        assert!(db.find_api_key("cp_unknown").await.unwrap().is_none());
+
+        db.record_api_key_usage(api_key.id, 10, 2).await.unwrap();
+        let listed = db.list_api_keys().await.unwrap();
+        assert_eq!(listed[0].request_count, 10);
+        assert_eq!(listed[0].rate_limited_count, 2);
+        assert!(listed[0].last_used_at.is_some());
+
+        assert!(db.revoke_api_key(api_key.id).await.unwrap());
+        assert!(!db.revoke_api_key(api_key.id).await.unwrap());
+        assert!(db.find_api_key(&key).await.unwrap().is_none());
+    }
+}
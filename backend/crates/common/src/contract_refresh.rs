@@ -0,0 +1,128 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::ContractDatabase;
+
+impl ContractDatabase {
+    /// The open contracts (without a close date) that weren't fetched since `fetched_before`,
+    /// starting with the ones whose execution deadline has passed, since they are the most
+    /// likely to have been closed, and then with the ones fetched the longest ago.
+    pub async fn list_contracts_to_refresh(
+        &self,
+        fetched_before: DateTime<Utc>,
+        limit: usize,
+    ) -> sqlx::Result<Vec<u64>> {
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM contracts
+            WHERE close_date IS NULL AND fetched_at < $1
+            ORDER BY
+                COALESCE(signing_date + execution_deadline_days < CURRENT_DATE, FALSE) DESC,
+                fetched_at ASC,
+                id ASC
+            LIMIT $2
+            "#,
+            fetched_before,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids.into_iter().map(|id| id as u64).collect())
+    }
+
+    /// Records that the contract was fetched, when it didn't change since it was saved.
+    pub async fn mark_contract_fetched(&self, id: u64) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE contracts SET fetched_at = NOW() WHERE id = $1",
+            id as i64
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{Contract, Currency};
+
+    fn test_contract(
+        id: u64,
+        signing_date: Option<NaiveDate>,
+        execution_deadline_days: usize,
+        close_date: Option<NaiveDate>,
+    ) -> Contract {
+        Contract {
+            id,
+            contracting_procedure_type: String::new(),
+            publication_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            signing_date,
+            ccp: false,
+            object_brief_description: String::new(),
+            initial_contractual_price: Currency(1000),
+            description: None,
+            contracting: Vec::new(),
+            contracted: Vec::new(),
+            cpvs: Vec::new(),
+            regime: None,
+            contract_status: None,
+            non_written_contract_justification_types: String::new(),
+            contract_types: String::new(),
+            execution_deadline_days,
+            execution_places: Vec::new(),
+            contract_fundamentation_type: String::new(),
+            contestants: Vec::new(),
+            invitees: Vec::new(),
+            documents: Vec::new(),
+            contracting_procedure_url: None,
+            announcement_id: None,
+            direct_award_fundamentation_type: String::new(),
+            observations: None,
+            end_of_contract_type: None,
+            close_date,
+            total_effective_price: None,
+            causes_deadline_change: None,
+            causes_price_change: None,
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_list_contracts_to_refresh(pg_pool: PgPool) -> sqlx::Result<()> {
+        let db = ContractDatabase::new(pg_pool);
+        let today = Utc::now().date_naive();
+        let last_year = today - Duration::days(365);
+
+        for contract in [
+            // still within the execution deadline
+            test_contract(1, Some(today), 30, None),
+            // past the execution deadline
+            test_contract(2, Some(last_year), 30, None),
+            test_contract(3, None, 30, None),
+            test_contract(4, Some(last_year), 30, Some(today)),
+        ] {
+            db.upsert_contract(&contract).await?;
+        }
+
+        let now = Utc::now();
+        assert_eq!(db.list_contracts_to_refresh(now, 10).await?, [2, 1, 3]);
+        assert_eq!(db.list_contracts_to_refresh(now, 1).await?, [2]);
+
+        db.mark_contract_fetched(2).await?;
+        assert_eq!(db.list_contracts_to_refresh(now, 10).await?, [1, 3]);
+
+        let an_hour_ago = now - Duration::hours(1);
+        assert!(
+            db.list_contracts_to_refresh(an_hour_ago, 10)
+                .await?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+}
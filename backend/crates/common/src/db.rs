@@ -1,6 +1,6 @@
 use anyhow::Context;
-use chrono::NaiveDate;
-use sqlx::PgPool;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgPool, postgres::PgListener};
 
 use crate::{Contract, Cpv, Currency, Document, Entity};
 
@@ -61,6 +61,10 @@ impl From<DocumentRow> for Document {
     }
 }
 
+/// The channel notified when contracts are saved, so that the API replicas can drop
+/// what they cached from the previous contracts (ex: search results).
+pub const CONTRACTS_CHANGED_CHANNEL: &str = "contracts_changed";
+
 #[derive(Debug, Clone)]
 pub struct ContractDatabase {
     pub(crate) pool: PgPool,
@@ -127,6 +131,39 @@ impl ContractDatabase {
         Ok(ids.into_iter().map(|id| id as u64).collect())
     }
 
+    /// The contracts saved since `since` (ex: while the search index is rebuilt).
+    pub async fn list_contract_ids_updated_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<u64>, sqlx::Error> {
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM contracts WHERE updated_at >= $1 ORDER BY id",
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids.into_iter().map(|id| id as u64).collect())
+    }
+
+    /// When the contract was last saved, which is cheaper to check than loading it.
+    pub async fn get_contract_updated_at(
+        &self,
+        id: u64,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        sqlx::query_scalar!("SELECT updated_at FROM contracts WHERE id = $1", id as i64)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// The publication date of the most recently published contract that was saved.
+    /// Read from the end of the index on the publication date, so it doesn't scan the contracts.
+    pub async fn get_latest_publication_date(&self) -> Result<Option<NaiveDate>, sqlx::Error> {
+        sqlx::query_scalar!("SELECT MAX(publication_date) FROM contracts")
+            .fetch_one(&self.pool)
+            .await
+    }
+
     pub async fn get_contract(&self, id: u64) -> Result<Option<Contract>, sqlx::Error> {
         let main: Option<ContractMainRow> = sqlx::query_as!(
             ContractMainRow,
@@ -277,7 +314,10 @@ impl ContractDatabase {
         }))
     }
 
-    pub async fn insert_contract(&self, contract: &Contract) -> Result<(), sqlx::Error> {
+    /// Inserts the contract, or replaces it if it was already saved (ex: when it is scraped again).
+    /// Returns the entities that were contracting or contracted before it was replaced, which
+    /// must also be updated (ex: their aggregates) if they were removed from the contract.
+    pub async fn upsert_contract(&self, contract: &Contract) -> Result<Vec<u64>, sqlx::Error> {
         let mut tx = self.pool.begin().await?;
 
         for cpv in &contract.cpvs {
@@ -328,7 +368,32 @@ impl ContractDatabase {
                 close_date, total_effective_price, causes_deadline_change, causes_price_change
             ) VALUES (
                 $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24
-            ) ON CONFLICT (id) DO NOTHING
+            ) ON CONFLICT (id) DO UPDATE SET
+                contracting_procedure_type = EXCLUDED.contracting_procedure_type,
+                publication_date = EXCLUDED.publication_date,
+                signing_date = EXCLUDED.signing_date,
+                ccp = EXCLUDED.ccp,
+                object_brief_description = EXCLUDED.object_brief_description,
+                initial_contractual_price = EXCLUDED.initial_contractual_price,
+                description = EXCLUDED.description,
+                regime = EXCLUDED.regime,
+                contract_status = EXCLUDED.contract_status,
+                non_written_contract_justification_types = EXCLUDED.non_written_contract_justification_types,
+                contract_types = EXCLUDED.contract_types,
+                execution_deadline_days = EXCLUDED.execution_deadline_days,
+                execution_places = EXCLUDED.execution_places,
+                contract_fundamentation_type = EXCLUDED.contract_fundamentation_type,
+                contracting_procedure_url = EXCLUDED.contracting_procedure_url,
+                announcement_id = EXCLUDED.announcement_id,
+                direct_award_fundamentation_type = EXCLUDED.direct_award_fundamentation_type,
+                observations = EXCLUDED.observations,
+                end_of_contract_type = EXCLUDED.end_of_contract_type,
+                close_date = EXCLUDED.close_date,
+                total_effective_price = EXCLUDED.total_effective_price,
+                causes_deadline_change = EXCLUDED.causes_deadline_change,
+                causes_price_change = EXCLUDED.causes_price_change,
+                updated_at = NOW(),
+                fetched_at = NOW()
             "#,
             contract.id as i64,
             contract.contracting_procedure_type,
@@ -360,6 +425,43 @@ impl ContractDatabase {
 
         let contract_id = contract.id as i64;
 
+        // the associations of a contract that was saved before are replaced
+        let mut previous_entity_ids = Vec::new();
+        for query in [
+            sqlx::query_scalar!(
+                "DELETE FROM contract_contracting WHERE contract_id = $1 RETURNING entity_id",
+                contract_id
+            ),
+            sqlx::query_scalar!(
+                "DELETE FROM contract_contracted WHERE contract_id = $1 RETURNING entity_id",
+                contract_id
+            ),
+        ] {
+            let entity_ids = query.fetch_all(&mut *tx).await?;
+            previous_entity_ids.extend(entity_ids.into_iter().map(|id| id as u64));
+        }
+
+        for query in [
+            sqlx::query!(
+                "DELETE FROM contract_contestants WHERE contract_id = $1",
+                contract_id
+            ),
+            sqlx::query!(
+                "DELETE FROM contract_invitees WHERE contract_id = $1",
+                contract_id
+            ),
+            sqlx::query!(
+                "DELETE FROM contract_documents WHERE contract_id = $1",
+                contract_id
+            ),
+            sqlx::query!(
+                "DELETE FROM contract_cpvs WHERE contract_id = $1",
+                contract_id
+            ),
+        ] {
+            query.execute(&mut *tx).await?;
+        }
+
         for entity in &contract.contracting {
             sqlx::query!(
                 "INSERT INTO contract_contracting (contract_id, entity_id, description) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
@@ -425,8 +527,30 @@ impl ContractDatabase {
         }
 
         tx.commit().await?;
+        Ok(previous_entity_ids)
+    }
+
+    /// Checks that the database is reachable.
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    pub async fn notify_contracts_changed(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_notify($1, '')")
+            .bind(CONTRACTS_CHANGED_CHANNEL)
+            .execute(&self.pool)
+            .await?;
+
         Ok(())
     }
+
+    /// Listens to [CONTRACTS_CHANGED_CHANNEL] on a dedicated connection.
+    pub async fn listen_contracts_changed(&self) -> Result<PgListener, sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CONTRACTS_CHANGED_CHANNEL).await?;
+        Ok(listener)
+    }
 }
 
 #[cfg(test)]
@@ -487,11 +611,45 @@ mod tests {
         let db = ContractDatabase::new(pg_pool);
 
         assert_eq!(None, db.get_contract(contract.id).await?);
+        assert_eq!(None, db.get_latest_publication_date().await?);
 
-        db.insert_contract(&contract).await?;
+        assert!(db.upsert_contract(&contract).await?.is_empty());
+        assert!(db.get_contract_updated_at(contract.id).await?.is_some());
+        assert_eq!(
+            Some(contract.publication_date),
+            db.get_latest_publication_date().await?
+        );
 
         let contract_from_db = db.get_contract(contract.id).await?;
-        assert_eq!(Some(contract), contract_from_db);
+        assert_eq!(Some(&contract), contract_from_db.as_ref());
+
+        let updated = Contract {
+            close_date: None,
+            contracted: vec![Entity {
+                id: 3,
+                nif: "111111111".to_string(),
+                description: "Another Contracted Entity".to_string(),
+            }],
+            ..contract
+        };
+        // the entity removed from the contract is returned, along with the kept one
+        let mut previous_entity_ids = db.upsert_contract(&updated).await?;
+        previous_entity_ids.sort();
+        assert_eq!(previous_entity_ids, vec![1, 2]);
+        assert_eq!(Some(updated), db.get_contract(1).await?);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_contracts_changed_notification(pg_pool: PgPool) -> sqlx::Result<()> {
+        let db = ContractDatabase::new(pg_pool);
+        let mut listener = db.listen_contracts_changed().await?;
+
+        db.notify_contracts_changed().await?;
+
+        let notification = listener.recv().await?;
+        assert_eq!(notification.channel(), super::CONTRACTS_CHANGED_CHANNEL);
 
         Ok(())
     }
@@ -0,0 +1,182 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// The name of the Meilisearch embedder used for the hybrid search of contracts.
+pub const CONTRACTS_EMBEDDER: &str = "contracts";
+
+/// The contract fields that are embedded, as a Meilisearch document template.
+const DOCUMENT_TEMPLATE: &str = "{{doc.objectBriefDescription}}\n{{doc.description}}";
+
+/// Text embedded to check the embedder and find out the dimensions of its embeddings.
+const TEST_TEXT: &str = "Aquisição de viaturas";
+
+#[derive(clap::Parser, Debug, Clone)]
+pub struct EmbedderConfig {
+    /// OpenAI compatible embeddings endpoint (ex: a local model server at
+    /// http://localhost:8080/v1/embeddings). Semantic search is disabled if not set.
+    #[clap(long, env)]
+    pub embedder_url: Option<String>,
+    #[clap(long, env)]
+    pub embedder_model: Option<String>,
+}
+
+impl EmbedderConfig {
+    pub fn create_embedder(&self) -> Option<Embedder> {
+        self.embedder_url.as_ref().map(|url| Embedder {
+            url: url.clone(),
+            model: self.embedder_model.clone(),
+            client: reqwest::Client::new(),
+        })
+    }
+}
+
+/// An HTTP embedder that follows the OpenAI embeddings API.
+/// Meilisearch calls it directly to embed documents and queries,
+/// this client is only used to check it and find out its dimensions.
+#[derive(Debug, Clone)]
+pub struct Embedder {
+    url: String,
+    model: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    input: &'a [&'a str],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// The settings of a Meilisearch `rest` embedder.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RestEmbedderSettings {
+    source: &'static str,
+    url: String,
+    request: Value,
+    response: Value,
+    document_template: &'static str,
+    dimensions: usize,
+}
+
+impl Embedder {
+    pub async fn embed(&self, texts: &[&str]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let request = EmbeddingRequest {
+            model: self.model.as_deref(),
+            input: texts,
+        };
+
+        let response: EmbeddingResponse = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .context("Failed to send embedding request")?
+            .error_for_status()
+            .context("Embedder returned an error")?
+            .json()
+            .await
+            .context("Failed to parse embedding response")?;
+
+        if response.data.len() != texts.len() {
+            anyhow::bail!(
+                "Embedder returned {} embeddings for {} texts",
+                response.data.len(),
+                texts.len()
+            );
+        }
+
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+
+    /// Embeds a test text to find out the dimensions of the embeddings.
+    pub async fn dimensions(&self) -> anyhow::Result<usize> {
+        let embeddings = self.embed(&[TEST_TEXT]).await?;
+
+        match embeddings.first() {
+            Some(embedding) if !embedding.is_empty() => Ok(embedding.len()),
+            _ => anyhow::bail!("Embedder returned an empty embedding"),
+        }
+    }
+
+    pub fn to_meilisearch(&self, dimensions: usize) -> RestEmbedderSettings {
+        let mut request = json!({ "input": ["{{text}}", "{{..}}"] });
+        if let Some(model) = &self.model {
+            request["model"] = json!(model);
+        }
+
+        RestEmbedderSettings {
+            source: "rest",
+            url: self.url.clone(),
+            request,
+            response: json!({ "data": [{ "embedding": "{{embedding}}" }, "{{..}}"] }),
+            document_template: DOCUMENT_TEMPLATE,
+            dimensions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, routing::post};
+
+    use super::*;
+
+    /// Embeds each text as its length repeated in every dimension.
+    async fn stub_embeddings(Json(request): Json<Value>) -> Json<Value> {
+        let data: Vec<Value> = request["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|text| {
+                let len = text.as_str().unwrap().len() as f32;
+                json!({ "embedding": [len, len, len] })
+            })
+            .collect();
+
+        Json(json!({ "data": data }))
+    }
+
+    async fn spawn_stub_embedder() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new().route("/v1/embeddings", post(stub_embeddings));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        format!("http://{addr}/v1/embeddings")
+    }
+
+    #[tokio::test]
+    async fn test_stub_embedder() {
+        let config = EmbedderConfig {
+            embedder_url: Some(spawn_stub_embedder().await),
+            embedder_model: Some("test-model".to_string()),
+        };
+        let embedder = config.create_embedder().unwrap();
+
+        let embeddings = embedder.embed(&["ab", "abcd"]).await.unwrap();
+        assert_eq!(embeddings, vec![vec![2.0; 3], vec![4.0; 3]]);
+        assert_eq!(embedder.dimensions().await.unwrap(), 3);
+
+        let settings = serde_json::to_value(embedder.to_meilisearch(3)).unwrap();
+        assert_eq!(settings["source"], "rest");
+        assert_eq!(settings["request"]["model"], "test-model");
+        assert_eq!(settings["dimensions"], 3);
+    }
+}
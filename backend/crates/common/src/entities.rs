@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::ContractDatabase;
+
+/// An entity (contracting or contracted) as saved in the meilisearch entities index,
+/// used to autocomplete entity names and NIFs.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchableEntity {
+    /// The internal Portal BASE identifier
+    pub id: u64,
+    pub nif: String,
+    /// The most used name of the entity across its contracts
+    pub name: String,
+    /// Every name the entity has been registered with
+    pub aliases: Vec<String>,
+    pub contracting_count: u64,
+    pub contracted_count: u64,
+    pub contract_count: u64,
+    /// The sum (in cents) of the initial contractual prices of the entity's contracts
+    pub total_value: i64,
+}
+
+struct SearchableEntityRow {
+    id: i64,
+    nif: String,
+    name: String,
+    aliases: Vec<String>,
+    contracting_count: i64,
+    contracted_count: i64,
+    contract_count: i64,
+    total_value: i64,
+}
+
+impl From<SearchableEntityRow> for SearchableEntity {
+    fn from(row: SearchableEntityRow) -> Self {
+        SearchableEntity {
+            id: row.id as u64,
+            nif: row.nif,
+            name: row.name,
+            aliases: row.aliases,
+            contracting_count: row.contracting_count as u64,
+            contracted_count: row.contracted_count as u64,
+            contract_count: row.contract_count as u64,
+            total_value: row.total_value,
+        }
+    }
+}
+
+impl ContractDatabase {
+    pub async fn list_entity_ids_after(
+        &self,
+        last_id: u64,
+        limit: usize,
+    ) -> Result<Vec<u64>, sqlx::Error> {
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM entities
+            WHERE id > $1
+            ORDER BY id
+            LIMIT $2
+            "#,
+            last_id as i64,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids.into_iter().map(|id| id as u64).collect())
+    }
+
+    /// Aggregates the contracts of the given entities where they are contracting or contracted,
+    /// where each contract is counted once (ex: if it is both contracting and contracted).
+    /// Entities without any of those contracts (ex: only contestants) are not returned.
+    pub async fn get_searchable_entities(
+        &self,
+        ids: &[u64],
+    ) -> Result<Vec<SearchableEntity>, sqlx::Error> {
+        let ids: Vec<i64> = ids.iter().map(|&id| id as i64).collect();
+
+        let rows = sqlx::query_as!(
+            SearchableEntityRow,
+            r#"
+            WITH roles AS (
+              SELECT entity_id, contract_id, description, TRUE AS contracting
+              FROM contract_contracting
+              WHERE entity_id = ANY($1)
+              UNION ALL
+              SELECT entity_id, contract_id, description, FALSE AS contracting
+              FROM contract_contracted
+              WHERE entity_id = ANY($1)
+            )
+            SELECT
+              e.id,
+              e.nif,
+              MODE() WITHIN GROUP (ORDER BY r.description) AS "name!",
+              ARRAY_AGG(DISTINCT r.description) AS "aliases!",
+              COUNT(DISTINCT r.contract_id) FILTER (WHERE r.contracting) AS "contracting_count!",
+              COUNT(DISTINCT r.contract_id) FILTER (WHERE NOT r.contracting) AS "contracted_count!",
+              COUNT(DISTINCT r.contract_id) AS "contract_count!",
+              (
+                SELECT COALESCE(SUM(c.initial_contractual_price), 0)
+                FROM contracts c
+                WHERE c.id IN (SELECT contract_id FROM roles WHERE entity_id = e.id)
+              )::BIGINT AS "total_value!"
+            FROM roles r
+            JOIN entities e ON e.id = r.entity_id
+            GROUP BY e.id, e.nif
+            "#,
+            &ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{Contract, Currency, Entity};
+
+    fn test_contract(id: u64, price: isize, contracting: Entity, contracted: Entity) -> Contract {
+        Contract {
+            id,
+            contracting_procedure_type: String::new(),
+            publication_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            signing_date: None,
+            ccp: false,
+            object_brief_description: String::new(),
+            initial_contractual_price: Currency(price),
+            description: None,
+            contracting: vec![contracting],
+            contracted: vec![contracted],
+            cpvs: Vec::new(),
+            regime: None,
+            contract_status: None,
+            non_written_contract_justification_types: String::new(),
+            contract_types: String::new(),
+            execution_deadline_days: 0,
+            execution_places: Vec::new(),
+            contract_fundamentation_type: String::new(),
+            contestants: Vec::new(),
+            invitees: Vec::new(),
+            documents: Vec::new(),
+            contracting_procedure_url: None,
+            announcement_id: None,
+            direct_award_fundamentation_type: String::new(),
+            observations: None,
+            end_of_contract_type: None,
+            close_date: None,
+            total_effective_price: None,
+            causes_deadline_change: None,
+            causes_price_change: None,
+        }
+    }
+
+    fn entity(id: u64, nif: &str, description: &str) -> Entity {
+        Entity {
+            id,
+            nif: nif.to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_get_searchable_entities(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+
+        let municipality = entity(1, "500000000", "Município de Lisboa");
+        let supplier = entity(2, "123456789", "Empresa XPTO, Lda.");
+
+        for (id, price, name) in [
+            (1, 1000, "Município de Lisboa"),
+            (2, 2000, "Município de Lisboa"),
+            (3, 3000, "Câmara Municipal de Lisboa"),
+        ] {
+            let contracting = entity(municipality.id, &municipality.nif, name);
+            let contract = test_contract(id, price, contracting, supplier.clone());
+            db.upsert_contract(&contract).await.unwrap();
+        }
+
+        // counted once, even though it is both contracting and contracted
+        let contract = test_contract(4, 4000, supplier.clone(), supplier.clone());
+        db.upsert_contract(&contract).await.unwrap();
+
+        let mut entities = db.get_searchable_entities(&[1, 2, 3]).await.unwrap();
+        entities.sort_by_key(|entity| entity.id);
+
+        assert_eq!(entities.len(), 2);
+
+        let municipality = &entities[0];
+        assert_eq!(municipality.name, "Município de Lisboa");
+        assert_eq!(
+            municipality.aliases,
+            vec!["Câmara Municipal de Lisboa", "Município de Lisboa"]
+        );
+        assert_eq!(municipality.contracting_count, 3);
+        assert_eq!(municipality.contracted_count, 0);
+        assert_eq!(municipality.total_value, 6000);
+
+        let supplier = &entities[1];
+        assert_eq!(supplier.contracting_count, 1);
+        assert_eq!(supplier.contracted_count, 4);
+        assert_eq!(supplier.contract_count, 4);
+        assert_eq!(supplier.total_value, 10000);
+
+        assert_eq!(db.list_entity_ids_after(1, 10).await.unwrap(), vec![2]);
+    }
+}
@@ -1,7 +1,16 @@
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
+pub mod admin_audit_log;
+pub mod api_keys;
+pub mod contract_refresh;
 pub mod db;
+pub mod embedder;
+pub mod entities;
+pub mod rate_limits;
+pub mod scrape_runs;
+pub mod search_settings;
 pub mod searchdb;
 pub mod statistics;
 
@@ -73,7 +82,11 @@ pub struct SearchableContract {
     pub contracting_procedure_type: String,
     pub publication_date: NaiveDate,
     pub signing_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub ccp: bool,
     pub object_brief_description: String,
+    /// Embedded along with the object brief description for semantic search
+    pub description: Option<String>,
     pub initial_contractual_price: Currency,
     pub contracting: Vec<Entity>,
     pub contracted: Vec<Entity>,
@@ -87,16 +100,87 @@ pub struct SearchableContract {
     pub documents: Vec<Document>,
     pub contracting_procedure_url: Option<String>,
     pub announcement_id: Option<usize>,
+    pub close_date: Option<NaiveDate>,
+    pub total_effective_price: Option<Currency>,
+    // derived fields used for faceting and filtering, defaulted so that
+    // documents indexed before they existed can still be deserialized
+    #[serde(default)]
+    pub publication_year: i32,
+    #[serde(default)]
+    pub cpv_divisions: Vec<String>,
+    #[serde(default)]
+    pub cpv_prefixes: Vec<String>,
+    #[serde(default)]
+    pub districts: Vec<String>,
+    #[serde(default)]
+    pub contestants_count: usize,
+    #[serde(default)]
+    pub has_documents: bool,
+}
+
+/// Returns the CPV division of a CPV code, which is represented by its first two digits.
+/// For example: "72212000-4" is in the "72" division (IT services).
+pub fn cpv_division(code: &str) -> Option<&str> {
+    code.get(..2)
+        .filter(|division| division.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Returns the prefixes of a CPV code that identify its division, group, class and category.
+/// For example: "72212000-4" has the prefixes "72", "722", "7221" and "72212".
+pub fn cpv_prefixes(code: &str) -> impl Iterator<Item = &str> {
+    let digits = code
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(code.len());
+    (2..=digits.min(5)).map(move |len| &code[..len])
+}
+
+/// Returns the district of an execution place.
+/// Execution places are formatted as "Country, District, Municipality" (ex: "Portugal, Porto, Maia").
+pub fn execution_place_district(place: &str) -> Option<&str> {
+    place
+        .split(',')
+        .nth(1)
+        .map(str::trim)
+        .filter(|district| !district.is_empty())
 }
 
 impl From<Contract> for SearchableContract {
     fn from(contract: Contract) -> Self {
+        let cpv_divisions = contract
+            .cpvs
+            .iter()
+            .filter_map(|cpv| cpv_division(&cpv.code))
+            .unique()
+            .map(str::to_string)
+            .collect();
+
+        let cpv_prefixes = contract
+            .cpvs
+            .iter()
+            .flat_map(|cpv| cpv_prefixes(&cpv.code))
+            .unique()
+            .map(str::to_string)
+            .collect();
+
+        let districts = contract
+            .execution_places
+            .iter()
+            .filter_map(|place| execution_place_district(place))
+            .unique()
+            .map(str::to_string)
+            .collect();
+
+        let contestants_count = contract.contestants.len();
+        let has_documents = !contract.documents.is_empty();
+
         SearchableContract {
             id: contract.id,
             contracting_procedure_type: contract.contracting_procedure_type,
             publication_date: contract.publication_date,
             signing_date: contract.signing_date,
+            ccp: contract.ccp,
             object_brief_description: contract.object_brief_description,
+            description: contract.description,
             initial_contractual_price: contract.initial_contractual_price,
             contracting: contract.contracting,
             contracted: contract.contracted,
@@ -110,6 +194,46 @@ impl From<Contract> for SearchableContract {
             documents: contract.documents,
             contracting_procedure_url: contract.contracting_procedure_url,
             announcement_id: contract.announcement_id,
+            close_date: contract.close_date,
+            total_effective_price: contract.total_effective_price,
+            publication_year: contract.publication_date.year(),
+            cpv_divisions,
+            cpv_prefixes,
+            districts,
+            contestants_count,
+            has_documents,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpv_division() {
+        assert_eq!(cpv_division("72212000-4"), Some("72"));
+        assert_eq!(cpv_division("4"), None);
+        assert_eq!(cpv_division("ab000000"), None);
+    }
+
+    #[test]
+    fn test_cpv_prefixes() {
+        assert_eq!(
+            cpv_prefixes("72212000-4").collect::<Vec<_>>(),
+            vec!["72", "722", "7221", "72212"]
+        );
+        assert_eq!(cpv_prefixes("7").count(), 0);
+    }
+
+    #[test]
+    fn test_execution_place_district() {
+        assert_eq!(
+            execution_place_district("Portugal, Porto, Maia"),
+            Some("Porto")
+        );
+        assert_eq!(execution_place_district("Portugal, Lisboa"), Some("Lisboa"));
+        assert_eq!(execution_place_district("Portugal"), None);
+        assert_eq!(execution_place_district("Portugal, , Maia"), None);
+    }
+}
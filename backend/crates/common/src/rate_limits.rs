@@ -0,0 +1,194 @@
+use crate::db::ContractDatabase;
+
+/// The state of a rate limit bucket after a request, with the times in microseconds
+/// since the unix epoch, so that every replica uses the clock of the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitBucket {
+    /// The theoretical arrival time of the next request
+    pub tat: i64,
+    pub now: i64,
+    pub allowed: bool,
+}
+
+impl ContractDatabase {
+    /// Counts a request against the bucket of `key` with the generic cell rate algorithm,
+    /// where a request is allowed if the bucket has room for it within `burst` requests.
+    pub async fn check_rate_limit(
+        &self,
+        key: &str,
+        period_micros: i64,
+        burst: i64,
+    ) -> sqlx::Result<RateLimitBucket> {
+        let allowed = sqlx::query!(
+            r#"
+            WITH clock AS (
+              SELECT (EXTRACT(EPOCH FROM CLOCK_TIMESTAMP()) * 1000000)::BIGINT AS now
+            )
+            INSERT INTO rate_limit_buckets AS b (key, tat)
+            SELECT $1, clock.now + $2 FROM clock
+            ON CONFLICT (key) DO UPDATE
+            SET tat = GREATEST(b.tat, (SELECT now FROM clock)) + $2
+            WHERE GREATEST(b.tat, (SELECT now FROM clock)) + $2 - $2 * $3 <= (SELECT now FROM clock)
+            RETURNING tat, (SELECT now FROM clock) AS "now!"
+            "#,
+            key,
+            period_micros,
+            burst
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(row) = allowed {
+            return Ok(RateLimitBucket {
+                tat: row.tat,
+                now: row.now,
+                allowed: true,
+            });
+        }
+
+        let row = sqlx::query!(
+            r#"
+            SELECT tat, (EXTRACT(EPOCH FROM CLOCK_TIMESTAMP()) * 1000000)::BIGINT AS "now!"
+            FROM rate_limit_buckets
+            WHERE key = $1
+            "#,
+            key
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(RateLimitBucket {
+            tat: row.tat,
+            now: row.now,
+            allowed: false,
+        })
+    }
+
+    /// The remaining microseconds of the penalty of `key`, if it is penalized.
+    pub async fn get_rate_limit_penalty(&self, key: &str) -> sqlx::Result<Option<i64>> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT (EXTRACT(EPOCH FROM until - NOW()) * 1000000)::BIGINT AS "remaining!"
+            FROM rate_limit_penalties
+            WHERE key = $1 AND until > NOW()
+            "#,
+            key
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Adds a violation to `key`, returning its number of violations since they were
+    /// last forgotten, which happens `reset_after_micros` after the last violation.
+    pub async fn add_rate_limit_violation(
+        &self,
+        key: &str,
+        reset_after_micros: i64,
+    ) -> sqlx::Result<i32> {
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO rate_limit_penalties AS p (key, violations, until, expires_at)
+            VALUES ($1, 1, NOW(), NOW() + $2 * INTERVAL '1 microsecond')
+            ON CONFLICT (key) DO UPDATE
+            SET violations = CASE WHEN p.expires_at > NOW() THEN p.violations + 1 ELSE 1 END,
+                expires_at = EXCLUDED.expires_at
+            RETURNING violations
+            "#,
+            key,
+            reset_after_micros as f64
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn set_rate_limit_penalty(
+        &self,
+        key: &str,
+        duration_micros: i64,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE rate_limit_penalties
+            SET until = NOW() + $2 * INTERVAL '1 microsecond'
+            WHERE key = $1
+            "#,
+            key,
+            duration_micros as f64
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the buckets that are full again and the forgotten violations.
+    pub async fn delete_expired_rate_limits(&self) -> sqlx::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM rate_limit_buckets
+            WHERE tat < (EXTRACT(EPOCH FROM CLOCK_TIMESTAMP()) * 1000000)::BIGINT
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query!("DELETE FROM rate_limit_penalties WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_rate_limit_bucket(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+        let period = 60_000_000;
+
+        let first = db.check_rate_limit("search:ip", period, 2).await.unwrap();
+        assert!(first.allowed);
+        assert_eq!(first.tat - first.now, period);
+
+        assert!(
+            db.check_rate_limit("search:ip", period, 2)
+                .await
+                .unwrap()
+                .allowed
+        );
+        let denied = db.check_rate_limit("search:ip", period, 2).await.unwrap();
+        assert!(!denied.allowed);
+        assert!(denied.tat > denied.now + period);
+
+        assert!(
+            db.check_rate_limit("search:other", period, 2)
+                .await
+                .unwrap()
+                .allowed
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_rate_limit_penalty(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+
+        assert_eq!(db.get_rate_limit_penalty("ip").await.unwrap(), None);
+        assert_eq!(
+            db.add_rate_limit_violation("ip", 60_000_000).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            db.add_rate_limit_violation("ip", 60_000_000).await.unwrap(),
+            2
+        );
+
+        db.set_rate_limit_penalty("ip", 10_000_000).await.unwrap();
+        let remaining = db.get_rate_limit_penalty("ip").await.unwrap().unwrap();
+        assert!(remaining > 0 && remaining <= 10_000_000);
+    }
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::ContractDatabase;
+
+impl ContractDatabase {
+    /// Records the start of a scrape, returning the id of the run.
+    pub async fn start_scrape_run(&self) -> sqlx::Result<i64> {
+        sqlx::query_scalar!("INSERT INTO scrape_runs DEFAULT VALUES RETURNING id")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Records the end of a scrape, which failed if there is an `error`.
+    pub async fn finish_scrape_run(&self, id: i64, error: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE scrape_runs SET finished_at = NOW(), error = $2 WHERE id = $1",
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_last_successful_scrape(&self) -> sqlx::Result<Option<DateTime<Utc>>> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT finished_at AS "finished_at!"
+            FROM scrape_runs
+            WHERE finished_at IS NOT NULL AND error IS NULL
+            ORDER BY finished_at DESC
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_scrape_runs(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+        assert_eq!(db.get_last_successful_scrape().await.unwrap(), None);
+
+        let failed = db.start_scrape_run().await.unwrap();
+        db.finish_scrape_run(failed, Some("Portal BASE is down"))
+            .await
+            .unwrap();
+        assert_eq!(db.get_last_successful_scrape().await.unwrap(), None);
+
+        let succeeded = db.start_scrape_run().await.unwrap();
+        db.start_scrape_run().await.unwrap();
+        db.finish_scrape_run(succeeded, None).await.unwrap();
+        assert!(db.get_last_successful_scrape().await.unwrap().is_some());
+    }
+}
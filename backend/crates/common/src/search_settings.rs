@@ -0,0 +1,82 @@
+use crate::db::ContractDatabase;
+
+impl ContractDatabase {
+    /// The search settings encoded as JSON, or None if they were never updated.
+    pub async fn get_search_settings(&self) -> sqlx::Result<Option<String>> {
+        sqlx::query_scalar!(r#"SELECT settings::TEXT AS "settings!" FROM search_settings"#)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Saves the search settings encoded as JSON, if the saved ones are still at
+    /// `previous_version`, or if they were never updated and `previous_version` is the version
+    /// of the settings used until then. Returns whether they were saved.
+    pub async fn save_search_settings(
+        &self,
+        previous_version: u32,
+        unsaved_version: u32,
+        version: u32,
+        settings: &str,
+    ) -> sqlx::Result<bool> {
+        if previous_version == unsaved_version {
+            let result = sqlx::query!(
+                r#"
+                INSERT INTO search_settings (version, settings)
+                VALUES ($1, $2::TEXT::JSONB)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+                version as i32,
+                settings
+            )
+            .execute(&self.pool)
+            .await?;
+            if result.rows_affected() == 1 {
+                return Ok(true);
+            }
+        }
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE search_settings
+            SET version = $2, settings = $3::TEXT::JSONB, updated_at = NOW()
+            WHERE version = $1
+            "#,
+            previous_version as i32,
+            version as i32,
+            settings
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_search_settings(pg_pool: PgPool) {
+        let db = ContractDatabase::new(pg_pool);
+        assert_eq!(db.get_search_settings().await.unwrap(), None);
+
+        assert!(!db.save_search_settings(2, 1, 3, "{}").await.unwrap());
+        assert!(
+            db.save_search_settings(1, 1, 2, r#"{"a": 2}"#)
+                .await
+                .unwrap()
+        );
+        assert!(!db.save_search_settings(1, 1, 2, "{}").await.unwrap());
+        assert!(
+            db.save_search_settings(2, 1, 3, r#"{"a": 3}"#)
+                .await
+                .unwrap()
+        );
+
+        let settings = db.get_search_settings().await.unwrap().unwrap();
+        assert_eq!(settings, r#"{"a": 3}"#);
+    }
+}
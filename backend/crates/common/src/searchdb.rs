@@ -1,8 +1,20 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use meilisearch_sdk::{client::Client, indexes::Index, task_info::TaskInfo};
+use meilisearch_sdk::{
+    client::Client,
+    indexes::Index,
+    request::{HttpClient, Method},
+    reqwest::ReqwestClient,
+    search::{SearchQuery, SearchResults},
+    task_info::TaskInfo,
+};
+use serde::{Serialize, de::DeserializeOwned};
 
-use crate::{Contract, SearchableContract};
+use crate::{
+    Contract, SearchableContract,
+    embedder::{CONTRACTS_EMBEDDER, RestEmbedderSettings},
+    entities::SearchableEntity,
+};
 
 #[derive(clap::Parser)]
 pub struct MeilisearchConfig {
@@ -24,25 +36,50 @@ impl MeilisearchConfig {
 #[derive(Debug, Clone)]
 pub struct SearchDatabase {
     client: Arc<Client>,
+    /// Used for the Meilisearch features that are not supported by [Client] yet.
+    http_client: ReqwestClient,
 }
 
 type MeilisearchError = meilisearch_sdk::errors::Error;
 
+/// A search query with the hybrid search parameters, which are not supported by [SearchQuery] yet.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HybridSearchQuery<'a> {
+    #[serde(flatten)]
+    query: &'a SearchQuery<'a, ReqwestClient>,
+    hybrid: HybridSearch,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HybridSearch {
+    embedder: &'static str,
+    semantic_ratio: f32,
+}
+
 impl SearchDatabase {
     pub fn new_from_config(config: MeilisearchConfig) -> Result<Self, MeilisearchError> {
-        Ok(Self::new(config.create_client()?))
+        Self::new(config.create_client()?)
     }
 
-    pub fn new(client: Client) -> Self {
-        Self {
+    pub fn new(client: Client) -> Result<Self, MeilisearchError> {
+        let http_client = ReqwestClient::new(client.get_api_key())?;
+
+        Ok(Self {
             client: Arc::new(client),
-        }
+            http_client,
+        })
     }
 
     pub fn index(&self) -> Index {
         self.client.index("contracts")
     }
 
+    pub fn entities_index(&self) -> Index {
+        self.client.index("entities")
+    }
+
     pub async fn save_contract(&self, contract: Contract) -> Result<TaskInfo, MeilisearchError> {
         let index = self.index();
         let searchable_contract: SearchableContract = contract.into();
@@ -52,6 +89,85 @@ impl SearchDatabase {
             .await
     }
 
+    pub async fn save_entities(
+        &self,
+        entities: &[SearchableEntity],
+    ) -> Result<TaskInfo, MeilisearchError> {
+        self.entities_index()
+            .add_documents(entities, Some("id"))
+            .await
+    }
+
+    /// Deletes the entities that are no longer contracting nor contracted in any contract.
+    pub async fn delete_entities(&self, ids: &[u64]) -> Result<TaskInfo, MeilisearchError> {
+        self.entities_index().delete_documents(ids).await
+    }
+
+    /// Registers the embedder used for semantic search in the contracts index,
+    /// or removes it if `None`. Meilisearch embeds every document again when it changes.
+    pub async fn set_embedder(
+        &self,
+        settings: Option<RestEmbedderSettings>,
+    ) -> Result<TaskInfo, MeilisearchError> {
+        self.raw_request(
+            &format!("indexes/{}/settings/embedders", self.index().uid),
+            Method::Patch {
+                query: (),
+                body: HashMap::from([(CONTRACTS_EMBEDDER, settings)]),
+            },
+            202,
+        )
+        .await
+    }
+
+    /// Executes a search in the contracts index mixing keyword and semantic search,
+    /// where `semantic_ratio` goes from 0 (keyword only) to 1 (semantic only).
+    pub async fn hybrid_search<T>(
+        &self,
+        query: &SearchQuery<'_, ReqwestClient>,
+        semantic_ratio: f32,
+    ) -> Result<SearchResults<T>, MeilisearchError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let body = HybridSearchQuery {
+            query,
+            hybrid: HybridSearch {
+                embedder: CONTRACTS_EMBEDDER,
+                semantic_ratio,
+            },
+        };
+
+        self.raw_request(
+            &format!("indexes/{}/search", self.index().uid),
+            Method::Post { query: (), body },
+            200,
+        )
+        .await
+    }
+
+    /// Sends a request to `path` (relative to the Meilisearch host) with the configured API key.
+    pub async fn raw_request<B, T>(
+        &self,
+        path: &str,
+        method: Method<(), B>,
+        expected_status_code: u16,
+    ) -> Result<T, MeilisearchError>
+    where
+        B: Serialize + Send + Sync,
+        T: DeserializeOwned + Send + 'static,
+    {
+        let url = format!(
+            "{}/{}",
+            self.client.get_host(),
+            path.trim_start_matches('/')
+        );
+
+        self.http_client
+            .request(&url, method, expected_status_code)
+            .await
+    }
+
     pub fn client(&self) -> &Client {
         &self.client
     }
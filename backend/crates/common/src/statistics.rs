@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::db::ContractDatabase;
 
-#[derive(Default, Clone, Debug, Deserialize, Serialize)]
+#[derive(Default, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Statistics {
     pub total_spent_last_365_days: i64,
@@ -110,7 +110,7 @@ mod tests {
             causes_price_change: None,
         };
 
-        contract_database.insert_contract(&contract).await.unwrap();
+        contract_database.upsert_contract(&contract).await.unwrap();
     }
 
     #[sqlx::test(migrations = "../../migrations")]
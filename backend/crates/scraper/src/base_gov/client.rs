@@ -3,12 +3,17 @@ use std::time::Duration;
 use anyhow::Context;
 use reqwest::Url;
 use serde::{Serialize, de::DeserializeOwned};
+use tokio::time::Instant;
 
 use crate::base_gov::{BaseGovContract, ContractSearchResponse};
 
 const URL: &str = "https://www.base.gov.pt/Base4/pt/resultados/";
 const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/140.0.0.0 Safari/537.36";
 
+/// The histogram of the latency of the requests to Portal BASE, whose buckets are set
+/// by the recorder since the requests are much slower than the ones to the backend.
+pub const REQUEST_DURATION_METRIC: &str = "base_gov_request_duration_seconds";
+
 #[derive(Debug, Clone, Copy)]
 pub struct ContractSort {
     pub method: ContractSortMethod,
@@ -45,6 +50,16 @@ pub enum BaseGovPayload {
     ContractDetails { version: &'static str, id: u64 },
 }
 
+impl BaseGovPayload {
+    /// The label of the request in the metrics.
+    fn kind(&self) -> &'static str {
+        match self {
+            BaseGovPayload::SearchContracts { .. } => "search",
+            BaseGovPayload::ContractDetails { .. } => "details",
+        }
+    }
+}
+
 pub struct BaseGovClient {
     client: reqwest::Client,
 }
@@ -93,19 +108,23 @@ impl BaseGovClient {
         &self,
         payload: BaseGovPayload,
     ) -> anyhow::Result<T> {
-        let response = self
-            .client
-            .post(URL)
-            .form(&payload)
-            .send()
-            .await
-            .context("Failed to send POST request")?;
+        let kind = payload.kind();
+        let start = Instant::now();
+
+        let response = self.client.post(URL).form(&payload).send().await;
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                // timeouts and connection failures, which have no status
+                record_request(kind, "error", start);
+                return Err(e).context("Failed to send POST request");
+            }
+        };
 
         let status = response.status();
-        let body = response
-            .text()
-            .await
-            .context("Failed to read response body")?;
+        let body = response.text().await;
+        record_request(kind, status.as_str(), start);
+        let body = body.context("Failed to read response body")?;
 
         let value = serde_json::from_str::<serde_json::Value>(&body).with_context(|| {
             format!("Failed to parse response as JSON (status {status}): {body}")
@@ -117,6 +136,16 @@ impl BaseGovClient {
     }
 }
 
+fn record_request(kind: &'static str, status: &str, start: Instant) {
+    let labels = [
+        ("request", kind.to_string()),
+        ("status", status.to_string()),
+    ];
+
+    metrics::counter!("base_gov_requests_total", &labels).increment(1);
+    metrics::histogram!(REQUEST_DURATION_METRIC, &labels).record(start.elapsed().as_secs_f64());
+}
+
 impl Serialize for ContractSort {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -4,6 +4,7 @@ use anyhow::Context;
 use clap::Parser;
 use common::{
     Contract,
+    api_keys::ApiKeyTier,
     db::{ContractDatabase, PostgresConfig},
     searchdb::{MeilisearchConfig, SearchDatabase},
 };
@@ -31,6 +32,26 @@ enum Command {
         saved_pages_path: PathBuf,
         base_gov_client_proxy: Option<Url>,
     },
+    /// Saves the contracts published since the newest one that was saved
+    PollNewContracts {
+        #[command(flatten)]
+        postgres_config: PostgresConfig,
+        #[command(flatten)]
+        meilisearch_config: MeilisearchConfig,
+        saved_pages_path: PathBuf,
+        base_gov_client_proxy: Option<Url>,
+    },
+    /// Fetches the open contracts again, saving the ones that changed
+    RefreshOpenContracts {
+        #[command(flatten)]
+        postgres_config: PostgresConfig,
+        #[command(flatten)]
+        meilisearch_config: MeilisearchConfig,
+        saved_pages_path: PathBuf,
+        base_gov_client_proxy: Option<Url>,
+        #[arg(long, default_value_t = scraper::refresh::REFRESH_BATCH_SIZE)]
+        limit: usize,
+    },
     FetchPage {
         #[arg(long, default_value = "id")]
         contract_sort_method: ContractSortMethod,
@@ -56,6 +77,24 @@ enum Command {
         #[command(flatten)]
         meilisearch_config: MeilisearchConfig,
     },
+    /// Issues an API key, which is only shown once
+    IssueApiKey {
+        #[command(flatten)]
+        postgres_config: PostgresConfig,
+        /// Who the key is issued to
+        name: String,
+        #[arg(long, default_value = "standard")]
+        tier: ApiKeyTier,
+    },
+    RevokeApiKey {
+        #[command(flatten)]
+        postgres_config: PostgresConfig,
+        id: i64,
+    },
+    ListApiKeys {
+        #[command(flatten)]
+        postgres_config: PostgresConfig,
+    },
 }
 
 #[tokio::main]
@@ -80,7 +119,44 @@ async fn main() -> anyhow::Result<()> {
                     .context("Failed to create store")?;
 
             let base_gov_client = BaseGovClient::new(base_gov_client_proxy);
-            scraper::scraper::scrape(Arc::new(store), base_gov_client).await;
+            let throttler = Arc::new(scraper::scraper::portal_base_throttler());
+            scraper::scraper::scrape(Arc::new(store), base_gov_client, throttler).await?;
+        }
+        Command::PollNewContracts {
+            saved_pages_path,
+            base_gov_client_proxy,
+            postgres_config,
+            meilisearch_config,
+        } => {
+            let search_database = SearchDatabase::new_from_config(meilisearch_config)?;
+            let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
+
+            let store =
+                scraper::store::Store::new(search_database, contract_database, saved_pages_path)
+                    .context("Failed to create store")?;
+
+            let base_gov_client = BaseGovClient::new(base_gov_client_proxy);
+            let throttler = scraper::scraper::portal_base_throttler();
+            scraper::scraper::poll_new_contracts(&store, &base_gov_client, &throttler).await?;
+        }
+        Command::RefreshOpenContracts {
+            saved_pages_path,
+            base_gov_client_proxy,
+            postgres_config,
+            meilisearch_config,
+            limit,
+        } => {
+            let search_database = SearchDatabase::new_from_config(meilisearch_config)?;
+            let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
+
+            let store =
+                scraper::store::Store::new(search_database, contract_database, saved_pages_path)
+                    .context("Failed to create store")?;
+
+            let base_gov_client = BaseGovClient::new(base_gov_client_proxy);
+            let throttler = scraper::scraper::portal_base_throttler();
+            scraper::refresh::refresh_open_contracts(&store, &base_gov_client, &throttler, limit)
+                .await?;
         }
         Command::FetchPage {
             contract_sort_method,
@@ -120,7 +196,7 @@ async fn main() -> anyhow::Result<()> {
             meilisearch_config,
         } => {
             let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
-            let search_database = SearchDatabase::new(meilisearch_config.create_client()?);
+            let search_database = SearchDatabase::new(meilisearch_config.create_client()?)?;
             tokio::select! {
                 result = search::rebuild::rebuild_search_index(&contract_database, &search_database) => result?,
                 _ = tokio::signal::ctrl_c() => {
@@ -128,6 +204,38 @@ async fn main() -> anyhow::Result<()> {
                 },
             }
         }
+        Command::IssueApiKey {
+            postgres_config,
+            name,
+            tier,
+        } => {
+            let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
+            let (api_key, key) = contract_database.issue_api_key(&name, tier).await?;
+
+            info!(
+                "Issued {} API key {} to {}",
+                api_key.tier, api_key.id, api_key.name
+            );
+            // printed instead of logged, so that the key isn't kept in the logs
+            println!("{key}");
+        }
+        Command::RevokeApiKey {
+            postgres_config,
+            id,
+        } => {
+            let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
+            if !contract_database.revoke_api_key(id).await? {
+                anyhow::bail!("API key {id} not found or already revoked");
+            }
+
+            info!("Revoked API key {id}");
+        }
+        Command::ListApiKeys { postgres_config } => {
+            let contract_database = ContractDatabase::new_from_config(postgres_config).await?;
+            for api_key in contract_database.list_api_keys().await? {
+                info!("{api_key:?}");
+            }
+        }
     }
 
     Ok(())
@@ -1,5 +1,6 @@
 pub mod base_gov;
 pub mod export;
+pub mod refresh;
 pub mod scraper;
 pub mod search;
 pub mod store;
@@ -0,0 +1,81 @@
+use chrono::Utc;
+use common::Contract;
+use log::{error, info};
+
+use crate::{
+    base_gov::client::BaseGovClient,
+    scraper::throttle::{Priority, Throttler},
+    store::Store,
+};
+
+/// How many open contracts are fetched again in each refresh, which takes a few minutes
+/// of the budget of Portal BASE.
+pub const REFRESH_BATCH_SIZE: usize = 50;
+/// How long the open contracts are left before being fetched again.
+const REFRESH_AFTER: chrono::Duration = chrono::Duration::days(7);
+
+/// Fetches the open contracts again, since some fields (ex: the close date and the total
+/// effective price) are only filled in when they are closed, saving the ones that changed.
+/// It has the same priority as the scrape, so that new contracts still go first.
+/// Returns how many contracts were updated.
+pub async fn refresh_open_contracts(
+    store: &Store,
+    base_gov_client: &BaseGovClient,
+    throttler: &Throttler,
+    limit: usize,
+) -> anyhow::Result<usize> {
+    let ids = store
+        .list_contracts_to_refresh(Utc::now() - REFRESH_AFTER, limit)
+        .await?;
+
+    let mut updated = 0;
+    for id in ids {
+        let response = {
+            let _permit = throttler.throttle(Priority::Low).await;
+            base_gov_client.get_contract_details(id).await
+        };
+
+        let contract: Contract = match response {
+            Ok(contract) => contract.into(),
+            Err(e) => {
+                error!("Failed to fetch details for open contract {id}:\n{e:?}");
+                metrics::counter!("scraper_refreshed_contracts_total", "result" => "failed")
+                    .increment(1);
+                // otherwise it would be the first one fetched in every refresh
+                store.mark_contract_fetched(id).await?;
+                continue;
+            }
+        };
+
+        let saved = store.get_contract(id).await?;
+        if saved.is_some_and(|saved| !closing_changed(&saved, &contract)) {
+            store.mark_contract_fetched(id).await?;
+            metrics::counter!("scraper_refreshed_contracts_total", "result" => "unchanged")
+                .increment(1);
+            continue;
+        }
+
+        store.save_contract(contract).await?;
+        metrics::counter!("scraper_refreshed_contracts_total", "result" => "updated").increment(1);
+        updated += 1;
+    }
+
+    store.finish_batch().await?;
+
+    if updated > 0 {
+        info!("Updated {updated} open contracts");
+    }
+
+    Ok(updated)
+}
+
+/// Whether the fields that are filled in when the contract is closed changed.
+/// The rest of the contract isn't compared, since the associations (ex: the entities) are
+/// read back in no particular order.
+fn closing_changed(saved: &Contract, fetched: &Contract) -> bool {
+    saved.close_date != fetched.close_date
+        || saved.total_effective_price != fetched.total_effective_price
+        || saved.end_of_contract_type != fetched.end_of_contract_type
+        || saved.causes_deadline_change != fetched.causes_deadline_change
+        || saved.causes_price_change != fetched.causes_price_change
+}
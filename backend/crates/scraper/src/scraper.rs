@@ -3,19 +3,28 @@ use crate::{
         self,
         client::{BaseGovClient, ContractSort},
     },
-    scraper::throttle::Throttler,
+    scraper::throttle::{Priority, Throttler},
     store::Store,
 };
+use anyhow::Context;
+use chrono::Utc;
 use governor::Quota;
 use log::{error, info, warn};
 use std::{sync::Arc, time::Duration};
-use tokio::task::JoinHandle;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
 
 pub const MAX_PAGE_SIZE: usize = 50;
 const CONTRACT_SORT_ORDER: ContractSort = base_gov::client::ContractSort {
     method: base_gov::client::ContractSortMethod::Id,
     order: base_gov::client::SortOrder::Ascending,
 };
+const NEWEST_FIRST_SORT_ORDER: ContractSort = base_gov::client::ContractSort {
+    method: base_gov::client::ContractSortMethod::Id,
+    order: base_gov::client::SortOrder::Descending,
+};
+/// How many pages are polled at most, since the older contracts are left to the scrape
+/// (ex: when nothing was saved yet).
+const MAX_POLLED_PAGES: usize = 5;
 
 // Max consecutive failures before giving up (stops the scrape when the API keeps failing)
 const MAX_CONSECUTIVE_FAILURES: usize = 3;
@@ -26,9 +35,125 @@ fn max_request_quota() -> Quota {
     Quota::with_period(Duration::from_secs(2)).unwrap()
 }
 
-pub async fn scrape(store: Arc<Store>, base_gov_client: BaseGovClient) {
+/// Throttles the requests to Portal BASE, which must be shared by everything that scrapes it
+/// (ex: the scrape and the polling for new contracts) so that they stay within its budget.
+pub fn portal_base_throttler() -> Throttler {
+    Throttler::new(MAX_CONCURRENT_REQUESTS, max_request_quota())
+}
+
+/// Scrapes the contracts that haven't been saved yet, recording the run in the database.
+/// It goes from the oldest contracts, so its requests have a low priority.
+pub async fn scrape(
+    store: Arc<Store>,
+    base_gov_client: BaseGovClient,
+    throttler: Arc<Throttler>,
+) -> anyhow::Result<()> {
+    let run_id = store.start_scrape_run().await?;
+
+    let result = scrape_pages(store.clone(), base_gov_client, throttler).await;
+
+    let error = result.as_ref().err().map(|e| format!("{e:#}"));
+    store.finish_scrape_run(run_id, error.as_deref()).await?;
+
+    record_publication_lag(&store).await;
+
+    result
+}
+
+/// Records how many days ago the most recently published contract that was saved was published,
+/// which grows if the scraper falls behind Portal BASE. It is cheap enough to record after every
+/// page, since the latest publication date is read from an index.
+async fn record_publication_lag(store: &Store) {
+    match store.get_latest_publication_date().await {
+        Ok(Some(latest)) => {
+            let lag = Utc::now().date_naive() - latest;
+            metrics::gauge!("scraper_publication_lag_days").set(lag.num_days() as f64);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to get the latest publication date: {e:?}"),
+    }
+}
+
+/// Records how many ids are waiting to have their details fetched.
+fn record_queue_depth(id_tx: &Sender<ContractLocation>) {
+    let depth = id_tx.max_capacity() - id_tx.capacity();
+    metrics::gauge!("scraper_id_queue_depth").set(depth as f64);
+}
+
+/// Saves the contracts published since the last poll, so that they don't wait for the scrape
+/// to reach them. It goes through the newest contracts until one was already saved, and saves
+/// them from the oldest, so that the ones missed if saving fails are polled again.
+/// A contract whose details fail to be fetched is skipped, and since the newer ones are still
+/// saved, the next poll stops before reaching it, so it is left to the scrape.
+/// Returns how many contracts were saved.
+pub async fn poll_new_contracts(
+    store: &Store,
+    base_gov_client: &BaseGovClient,
+    throttler: &Throttler,
+) -> anyhow::Result<usize> {
+    let mut new_ids = Vec::new();
+
+    'pages: for page in 0..MAX_POLLED_PAGES {
+        let response = {
+            let _permit = throttler.throttle(Priority::High).await;
+            base_gov_client
+                .fetch_page(NEWEST_FIRST_SORT_ORDER, page, MAX_PAGE_SIZE)
+                .await
+                .with_context(|| format!("Failed to fetch page {page} of the newest contracts"))?
+        };
+        metrics::counter!("scraper_pages_fetched_total").increment(1);
+
+        let last_page = response.items.len() < MAX_PAGE_SIZE;
+        for minimal_contract in response.items {
+            if store.is_saved(minimal_contract.id).await? {
+                break 'pages;
+            }
+            new_ids.push(minimal_contract.id);
+        }
+
+        if last_page {
+            break;
+        }
+    }
+
+    let mut saved = 0;
+    for &id in new_ids.iter().rev() {
+        let response = {
+            let _permit = throttler.throttle(Priority::High).await;
+            base_gov_client.get_contract_details(id).await
+        };
+
+        let contract = match response {
+            Ok(contract) => contract.into(),
+            Err(e) => {
+                // left to the scrape, since it would otherwise block the newer contracts
+                error!("Failed to fetch details for new contract {id}:\n{e:?}");
+                metrics::counter!("scraper_failures_total", "stage" => "contract").increment(1);
+                continue;
+            }
+        };
+
+        store.save_contract(contract).await?;
+        metrics::counter!("scraper_contracts_saved_total").increment(1);
+        saved += 1;
+    }
+
+    store.finish_batch().await?;
+
+    if saved > 0 {
+        info!("Saved {saved} new contracts");
+        record_publication_lag(store).await;
+    }
+
+    Ok(saved)
+}
+
+async fn scrape_pages(
+    store: Arc<Store>,
+    base_gov_client: BaseGovClient,
+    throttler: Arc<Throttler>,
+) -> anyhow::Result<()> {
     let client = Arc::new(base_gov_client);
-    let throttler = Arc::new(Throttler::new(MAX_CONCURRENT_REQUESTS, max_request_quota()));
 
     let (id_tx, id_rx) = tokio::sync::mpsc::channel(MAX_CONCURRENT_REQUESTS);
     let (exit_tx, exit_rx) = tokio::sync::oneshot::channel();
@@ -42,7 +167,8 @@ pub async fn scrape(store: Arc<Store>, base_gov_client: BaseGovClient) {
     );
     let details_task = run_fetch_details_task(client, store, throttler, exit_rx, id_tx, id_rx);
 
-    tokio::join!(fetch_task, details_task);
+    let (result, _) = tokio::join!(fetch_task, details_task);
+    result
 }
 
 struct ContractLocation {
@@ -57,14 +183,19 @@ async fn run_fetch_ids_task(
     throttler: Arc<Throttler>,
     exit_tx: tokio::sync::oneshot::Sender<()>,
     id_tx: tokio::sync::mpsc::Sender<ContractLocation>,
-) {
+) -> anyhow::Result<()> {
     let mut total_pages = None;
+    let mut result = Ok(());
     let mut consecutive_failures = 0_usize;
     let mut current_page = 0_usize;
 
     loop {
         if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
             error!("Couldn't fetch IDs for {MAX_CONSECUTIVE_FAILURES} consecutive times, stopping");
+            metrics::counter!("scraper_failures_total", "stage" => "page").increment(1);
+            result = Err(anyhow::anyhow!(
+                "Couldn't fetch IDs of page {current_page} for {MAX_CONSECUTIVE_FAILURES} consecutive times"
+            ));
             break;
         }
 
@@ -81,7 +212,7 @@ async fn run_fetch_ids_task(
         info!("Fetching page {current_page}/{total_pages_str}...");
 
         let response = {
-            let _permit = throttler.throttle().await;
+            let _permit = throttler.throttle(Priority::Low).await;
             client
                 .fetch_page(CONTRACT_SORT_ORDER, current_page, MAX_PAGE_SIZE)
                 .await
@@ -92,6 +223,9 @@ async fn run_fetch_ids_task(
             Err(e) => {
                 error!("Failed to fetch IDs page {current_page}:\n{e:?}");
                 consecutive_failures += 1;
+                if consecutive_failures < MAX_CONSECUTIVE_FAILURES {
+                    metrics::counter!("scraper_retries_total", "stage" => "page").increment(1);
+                }
                 continue;
             }
         };
@@ -100,6 +234,7 @@ async fn run_fetch_ids_task(
             "Fetched page {current_page} with {} contracts",
             response.items.len()
         );
+        metrics::counter!("scraper_pages_fetched_total").increment(1);
 
         let minimal_contracts = response.items;
 
@@ -113,6 +248,7 @@ async fn run_fetch_ids_task(
                     retries: 0,
                 })
                 .await;
+            record_queue_depth(&id_tx);
         }
 
         let new_total_pages = response.total / MAX_PAGE_SIZE;
@@ -125,6 +261,7 @@ async fn run_fetch_ids_task(
     }
 
     let _ = exit_tx.send(());
+    result
 }
 
 async fn run_fetch_details_task(
@@ -136,6 +273,7 @@ async fn run_fetch_details_task(
     mut id_rx: tokio::sync::mpsc::Receiver<ContractLocation>,
 ) {
     let mut handles: Vec<JoinHandle<()>> = Vec::new();
+    let mut last_page = None;
 
     loop {
         let ContractLocation { id, page, retries } = tokio::select! {
@@ -147,15 +285,28 @@ async fn run_fetch_details_task(
                 break;
             }
         };
+        record_queue_depth(&id_tx);
 
-        if store.already_exists(id, page).await {
-            warn!("Contract {id} already exists, skipping...");
-            continue;
+        // the contracts are saved in batches of a page, since their entities are aggregated
+        // from every contract
+        if last_page.is_some_and(|last_page| page > last_page) {
+            finish_batch(&store).await;
+        }
+        last_page = last_page.max(Some(page));
+
+        match store.already_exists(id, page, MAX_PAGE_SIZE).await {
+            Ok(true) => {
+                warn!("Contract {id} already exists, skipping...");
+                continue;
+            }
+            Ok(false) => {}
+            // fetched again, which is only wasteful
+            Err(e) => warn!("Failed to check if contract {id} exists:\n{e:?}"),
         }
 
         handles.retain(|task| !task.is_finished());
 
-        let permit = throttler.throttle().await;
+        let permit = throttler.throttle(Priority::Low).await;
         let client = Arc::clone(&client);
         let store = Arc::clone(&store);
         let id_tx = id_tx.clone();
@@ -176,12 +327,17 @@ async fn run_fetch_details_task(
                             "Failed to fetch details for ID {id} after {} retries:\n{e:?}",
                             MAX_CONSECUTIVE_FAILURES
                         );
+                        metrics::counter!("scraper_failures_total", "stage" => "contract")
+                            .increment(1);
                         // do not retry
                     } else {
                         error!("Failed to fetch details for ID {id}:\n{:?}", e);
+                        metrics::counter!("scraper_retries_total", "stage" => "contract")
+                            .increment(1);
                         // Enqueue the ID for retry
                         drop(_permit);
                         let _ = id_tx.send(ContractLocation { id, page, retries }).await;
+                        record_queue_depth(&id_tx);
                     }
 
                     return;
@@ -191,11 +347,15 @@ async fn run_fetch_details_task(
             let contract = contract.into();
             info!("Fetched details for contract {id}");
 
-            if let Err(e) = store
+            match store
                 .save_scraped_contract(contract, page, MAX_PAGE_SIZE)
                 .await
             {
-                error!("Failed to save details for ID {id}:\n{:?}", e);
+                Ok(()) => metrics::counter!("scraper_contracts_saved_total").increment(1),
+                Err(e) => {
+                    error!("Failed to save details for ID {id}:\n{:?}", e);
+                    metrics::counter!("scraper_failures_total", "stage" => "save").increment(1);
+                }
             }
         });
 
@@ -205,35 +365,144 @@ async fn run_fetch_details_task(
     for handle in handles {
         let _ = handle.await;
     }
+
+    finish_batch(&store).await;
+}
+
+/// Finishes the batch of the contracts saved so far, where a failure is only logged since
+/// it is retried with the next batch. The publication lag is also recorded, so that it is
+/// up to date during long scrapes.
+async fn finish_batch(store: &Store) {
+    if let Err(e) = store.finish_batch().await {
+        error!("Failed to update entities:\n{e:?}");
+        metrics::counter!("scraper_failures_total", "stage" => "entities").increment(1);
+    }
+
+    record_publication_lag(store).await;
 }
 
 pub mod throttle {
-    use std::sync::Arc;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
 
     use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
-    use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+    use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Priority {
+        /// Waits for every high priority request (ex: the scrape of the older contracts)
+        Low,
+        /// Goes before every low priority request (ex: polling for new contracts)
+        High,
+    }
 
     pub struct Throttler {
         rate_limiter: DefaultDirectRateLimiter,
         semaphore: Arc<Semaphore>,
+        high_priority_waiting: AtomicUsize,
+        high_priority_served: Notify,
     }
 
     pub struct Permit {
         _inner: OwnedSemaphorePermit,
     }
 
+    /// Counts a high priority request as waiting until it is served or cancelled.
+    struct HighPriorityWaiting<'a>(&'a Throttler);
+
+    impl<'a> HighPriorityWaiting<'a> {
+        fn new(throttler: &'a Throttler) -> Self {
+            throttler
+                .high_priority_waiting
+                .fetch_add(1, Ordering::SeqCst);
+            Self(throttler)
+        }
+    }
+
+    impl Drop for HighPriorityWaiting<'_> {
+        fn drop(&mut self) {
+            if self.0.high_priority_waiting.fetch_sub(1, Ordering::SeqCst) == 1 {
+                self.0.high_priority_served.notify_waiters();
+            }
+        }
+    }
+
     impl Throttler {
         pub fn new(max_concurrent: usize, rate_limit_quota: Quota) -> Self {
             Throttler {
                 rate_limiter: RateLimiter::direct(rate_limit_quota),
                 semaphore: Arc::new(Semaphore::new(max_concurrent)),
+                high_priority_waiting: AtomicUsize::new(0),
+                high_priority_served: Notify::new(),
+            }
+        }
+
+        pub async fn throttle(&self, priority: Priority) -> Permit {
+            match priority {
+                Priority::High => {
+                    let _waiting = HighPriorityWaiting::new(self);
+                    let permit = self.semaphore.clone().acquire_owned().await.unwrap();
+                    self.permit(permit).await
+                }
+                Priority::Low => loop {
+                    self.wait_for_high_priority().await;
+
+                    let permit = self.semaphore.clone().acquire_owned().await.unwrap();
+                    // a high priority request may have arrived while waiting for the permit
+                    if self.high_priority_waiting.load(Ordering::SeqCst) == 0 {
+                        return self.permit(permit).await;
+                    }
+                },
             }
         }
 
-        pub async fn throttle(&self) -> Permit {
+        async fn wait_for_high_priority(&self) {
+            loop {
+                // created before checking, so that it isn't missed if notified meanwhile
+                let served = self.high_priority_served.notified();
+                if self.high_priority_waiting.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                served.await;
+            }
+        }
+
+        async fn permit(&self, permit: OwnedSemaphorePermit) -> Permit {
             self.rate_limiter.until_ready().await;
-            let permit = self.semaphore.clone().acquire_owned().await.unwrap();
             Permit { _inner: permit }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use tokio::sync::mpsc;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_high_priority_goes_first() {
+            let quota = Quota::per_second(nonzero_ext::nonzero!(1000u32));
+            let throttler = Arc::new(Throttler::new(1, quota));
+            let (served_tx, mut served_rx) = mpsc::unbounded_channel();
+
+            let permit = throttler.throttle(Priority::Low).await;
+            for priority in [Priority::Low, Priority::High] {
+                let throttler = throttler.clone();
+                let served_tx = served_tx.clone();
+                tokio::spawn(async move {
+                    let _permit = throttler.throttle(priority).await;
+                    served_tx.send(priority).unwrap();
+                });
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            drop(permit);
+
+            assert_eq!(served_rx.recv().await, Some(Priority::High));
+            assert_eq!(served_rx.recv().await, Some(Priority::Low));
+        }
+    }
 }
@@ -1,14 +1,23 @@
-use std::time::Duration;
+use std::{collections::BTreeSet, time::Duration};
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use common::{SearchableContract, db::ContractDatabase, searchdb::SearchDatabase};
 use futures::{StreamExt, TryStreamExt, stream};
 use log::info;
-use meilisearch_sdk::client::SwapIndexes;
+use meilisearch_sdk::{
+    client::{Client, SwapIndexes},
+    indexes::Index,
+    request::Method,
+    task_info::TaskInfo,
+};
 use tokio::time::Instant;
 
 const FETCH_CONCURRENCY: usize = 10;
 const BATCH_SIZE: usize = 5000;
+/// Subtracted from when the contracts saved during a rebuild are looked up, so that the ones
+/// saved right then aren't missed (ex: due to clock differences with the database).
+const REPLAY_MARGIN: chrono::Duration = chrono::Duration::minutes(1);
 
 async fn load_contracts_parallel(
     contract_database: &ContractDatabase,
@@ -32,6 +41,9 @@ async fn load_contracts_parallel(
     .await
 }
 
+/// Rebuilds the search indexes from the database into new indexes that replace them when
+/// complete. The contracts saved meanwhile (ex: by the scraper) are indexed again around the swap,
+/// so the scraper doesn't have to be stopped.
 pub async fn rebuild_search_index(
     contract_database: &ContractDatabase,
     search_database: &SearchDatabase,
@@ -43,12 +55,17 @@ pub async fn rebuild_search_index(
         .await
         .context("Failed to get indexes")?;
 
-    for index in indexes.results {
-        let index_name = index.uid;
+    let index_names = indexes
+        .results
+        .into_iter()
+        .map(|index| index.uid)
+        .collect::<Vec<_>>();
+
+    for index_name in &index_names {
         if index_name.starts_with("migration_") {
             info!("Found unremoved migration index '{index_name}'. Deleting...");
             client
-                .delete_index(&index_name)
+                .delete_index(index_name)
                 .await
                 .context("Failed to delete migration index")?
                 .wait_for_completion(client, None, Some(Duration::from_mins(30)))
@@ -58,9 +75,11 @@ pub async fn rebuild_search_index(
 
     let current_time_str = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
     let index_name = format!("migration_{current_time_str}");
-    let index = client.index(&index_name);
+    let index =
+        create_rebuild_index(search_database, &index_names, "contracts", &index_name).await?;
 
     info!("Rebuilding search index into {index_name}");
+    let started_at = Utc::now() - REPLAY_MARGIN;
 
     let mut last_id = 0_u64;
     let mut total_indexed = 0_usize;
@@ -102,31 +121,265 @@ pub async fn rebuild_search_index(
     for task in add_documents_tasks {
         task.wait_for_completion(client, None, Some(Duration::from_hours(1)))
             .await
-            .with_context(|| format!("Failed to complete add_documents task"))?;
+            .context("Failed to complete add_documents task")?;
+    }
+
+    let replayed_at = Utc::now() - REPLAY_MARGIN;
+    replay_saved_contracts(contract_database, &index, started_at).await?;
+    swap_rebuilt_index(client, &index_name, "contracts").await?;
+    // the ones saved since the replay were saved into the old index
+    replay_saved_contracts(contract_database, &client.index("contracts"), replayed_at).await?;
+
+    // the cached search results were computed from the old index
+    contract_database
+        .notify_contracts_changed()
+        .await
+        .context("Failed to notify that the search index was rebuilt")?;
+
+    info!("Finished rebuilding search index {index_name} with {total_indexed} contracts");
+
+    rebuild_entities_index(contract_database, search_database, &index_names).await
+}
+
+/// Rebuilds the entities index from the entities saved in the database.
+async fn rebuild_entities_index(
+    contract_database: &ContractDatabase,
+    search_database: &SearchDatabase,
+    index_names: &[String],
+) -> anyhow::Result<()> {
+    let client = search_database.client();
+
+    let current_time_str = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
+    let index_name = format!("migration_entities_{current_time_str}");
+    let index = create_rebuild_index(search_database, index_names, "entities", &index_name).await?;
+
+    info!("Rebuilding entities index into {index_name}");
+    let started_at = Utc::now() - REPLAY_MARGIN;
+
+    let mut last_id = 0_u64;
+    let mut total_indexed = 0_usize;
+
+    let mut add_documents_tasks = Vec::new();
+
+    loop {
+        let ids = contract_database
+            .list_entity_ids_after(last_id, BATCH_SIZE)
+            .await
+            .context("Failed to list entity ids for search rebuild")?;
+
+        let Some(&batch_last_id) = ids.last() else {
+            break;
+        };
+
+        let entities = contract_database
+            .get_searchable_entities(&ids)
+            .await
+            .with_context(|| {
+                format!("Failed to load entities batch ending at id {batch_last_id}")
+            })?;
+
+        let task = index
+            .add_documents(&entities, Some("id"))
+            .await
+            .with_context(|| {
+                format!("Failed to save entities batch ending at id {batch_last_id}")
+            })?;
+
+        add_documents_tasks.push(task);
+
+        last_id = batch_last_id;
+        total_indexed += entities.len();
+
+        info!("Indexed {total_indexed} entities into {index_name}");
+    }
+
+    info!("Waiting for {total_indexed} add_documents tasks to complete...");
+    for task in add_documents_tasks {
+        task.wait_for_completion(client, None, Some(Duration::from_hours(1)))
+            .await
+            .context("Failed to complete add_documents task")?;
+    }
+
+    let replayed_at = Utc::now() - REPLAY_MARGIN;
+    replay_saved_entities(contract_database, &index, started_at).await?;
+    swap_rebuilt_index(client, &index_name, "entities").await?;
+    // the ones saved since the replay were saved into the old index
+    replay_saved_entities(contract_database, &client.index("entities"), replayed_at).await?;
+
+    info!("Finished rebuilding entities index {index_name} with {total_indexed} entities");
+
+    Ok(())
+}
+
+/// Creates the index that `target` is rebuilt into, with the settings of `target` (ex: the
+/// sortable attributes), so that it can be swapped even if no document is added.
+/// `target` is also created if it doesn't exist yet, since only existing indexes can be swapped.
+async fn create_rebuild_index(
+    search_database: &SearchDatabase,
+    index_names: &[String],
+    target: &str,
+    index_name: &str,
+) -> anyhow::Result<Index> {
+    let client = search_database.client();
+
+    client
+        .create_index(index_name, Some("id"))
+        .await
+        .with_context(|| format!("Failed to create index {index_name}"))?
+        .wait_for_completion(client, None, Some(Duration::from_mins(30)))
+        .await?;
+
+    if index_names.iter().any(|name| name == target) {
+        copy_settings(search_database, target, index_name).await?;
+    } else {
+        client
+            .create_index(target, Some("id"))
+            .await
+            .with_context(|| format!("Failed to create index {target}"))?
+            .wait_for_completion(client, None, Some(Duration::from_mins(30)))
+            .await?;
+    }
+
+    Ok(client.index(index_name))
+}
+
+/// Copies the settings of `source` into the index being rebuilt before any document is added.
+/// This includes the semantic search embedder, so documents are embedded as they are added
+/// and the rebuilt index is fully embedded by the time it is swapped.
+async fn copy_settings(
+    search_database: &SearchDatabase,
+    source: &str,
+    target: &str,
+) -> anyhow::Result<()> {
+    // the raw settings are used since [meilisearch_sdk::settings::Settings] doesn't have embedders
+    let settings: serde_json::Value = search_database
+        .raw_request::<(), _>(
+            &format!("indexes/{source}/settings"),
+            Method::Get { query: () },
+            200,
+        )
+        .await
+        .with_context(|| format!("Failed to get settings of {source}"))?;
+
+    let task: TaskInfo = search_database
+        .raw_request(
+            &format!("indexes/{target}/settings"),
+            Method::Patch {
+                query: (),
+                body: settings,
+            },
+            202,
+        )
+        .await
+        .with_context(|| format!("Failed to copy settings of {source} into {target}"))?;
+
+    task.wait_for_completion(
+        search_database.client(),
+        None,
+        Some(Duration::from_mins(30)),
+    )
+    .await?;
+
+    info!("Copied settings of {source} into {target}");
+
+    Ok(())
+}
+
+/// Indexes the contracts saved since `since` again, since they were saved into the index
+/// that is being replaced (ex: by the scraper, while the index was rebuilt).
+async fn replay_saved_contracts(
+    contract_database: &ContractDatabase,
+    index: &Index,
+    since: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let ids = contract_database
+        .list_contract_ids_updated_since(since)
+        .await
+        .context("Failed to list the contracts saved during the rebuild")?;
+
+    for ids in ids.chunks(BATCH_SIZE) {
+        let contracts = load_contracts_parallel(contract_database, ids).await?;
+        index
+            .add_documents(&contracts, Some("id"))
+            .await
+            .context("Failed to save the contracts saved during the rebuild")?
+            .wait_for_completion(&index.client, None, Some(Duration::from_hours(1)))
+            .await?;
+    }
+
+    info!("Replayed {} contracts saved during the rebuild", ids.len());
+
+    Ok(())
+}
+
+/// Indexes the entities of the contracts saved since `since` again, as in
+/// [replay_saved_contracts].
+async fn replay_saved_entities(
+    contract_database: &ContractDatabase,
+    index: &Index,
+    since: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let contract_ids = contract_database
+        .list_contract_ids_updated_since(since)
+        .await
+        .context("Failed to list the contracts saved during the rebuild")?;
+
+    let mut entity_ids = BTreeSet::new();
+    for contract_ids in contract_ids.chunks(BATCH_SIZE) {
+        let contracts = load_contracts_parallel(contract_database, contract_ids).await?;
+        entity_ids.extend(
+            contracts
+                .iter()
+                .flat_map(|contract| contract.contracting.iter().chain(&contract.contracted))
+                .map(|entity| entity.id),
+        );
+    }
+
+    let entity_ids = entity_ids.into_iter().collect::<Vec<_>>();
+    for entity_ids in entity_ids.chunks(BATCH_SIZE) {
+        let entities = contract_database
+            .get_searchable_entities(entity_ids)
+            .await
+            .context("Failed to load the entities saved during the rebuild")?;
+        index
+            .add_documents(&entities, Some("id"))
+            .await
+            .context("Failed to save the entities saved during the rebuild")?
+            .wait_for_completion(&index.client, None, Some(Duration::from_hours(1)))
+            .await?;
     }
 
+    info!(
+        "Replayed {} entities saved during the rebuild",
+        entity_ids.len()
+    );
+
+    Ok(())
+}
+
+/// Swaps the rebuilt index into `target` and deletes the old index (now stored at `index_name`).
+async fn swap_rebuilt_index(client: &Client, index_name: &str, target: &str) -> anyhow::Result<()> {
     let swap_indexes = SwapIndexes {
-        indexes: (index_name.clone(), "contracts".to_string()),
+        indexes: (index_name.to_string(), target.to_string()),
     };
 
     client
         .swap_indexes([&swap_indexes])
         .await
-        .with_context(|| format!("Failed to swap rebuilt index {index_name} into contracts"))?
+        .with_context(|| format!("Failed to swap rebuilt index {index_name} into {target}"))?
         .wait_for_completion(client, None, Some(Duration::from_mins(30)))
         .await?;
 
-    info!("Swapped rebuilt index {index_name} into contracts");
+    info!("Swapped rebuilt index {index_name} into {target}");
 
     client
-        .delete_index(&index_name)
+        .delete_index(index_name)
         .await
-        .with_context(|| format!("Failed to delete old contracts index at {index_name}"))?
+        .with_context(|| format!("Failed to delete old {target} index at {index_name}"))?
         .wait_for_completion(client, None, Some(Duration::from_mins(30)))
         .await?;
 
-    info!("Deleted old contracts index now stored at {index_name}");
-    info!("Finished rebuilding search index {index_name} with {total_indexed} contracts");
+    info!("Deleted old {target} index now stored at {index_name}");
 
     Ok(())
 }
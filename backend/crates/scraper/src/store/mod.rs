@@ -6,7 +6,9 @@ use std::{
 };
 
 use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
 use common::{Contract, db::ContractDatabase, searchdb::SearchDatabase};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
 use crate::store::rangeset::RangeSet;
@@ -18,10 +20,18 @@ pub struct Store {
     contract_database: ContractDatabase,
     scrape_progress: Mutex<ScrapeProgress>,
     path: PathBuf,
+    /// The contracts saved since the last [Store::finish_batch]
+    pending_batch: Mutex<PendingBatch>,
+}
+
+#[derive(Debug, Default)]
+struct PendingBatch {
+    contracts_saved: bool,
+    entity_ids: HashSet<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
-struct ScrapeProgress {
+pub struct ScrapeProgress {
     /// An optimized set of the pages that have been scraped and saved
     saved_pages: RangeSet<usize>,
     /// A map of page (that have not been completely scraped yet) numbers
@@ -30,6 +40,14 @@ struct ScrapeProgress {
 }
 
 impl ScrapeProgress {
+    pub fn saved_pages(&self) -> &RangeSet<usize> {
+        &self.saved_pages
+    }
+
+    pub fn pending_pages(&self) -> &HashMap<usize, HashSet<u64>> {
+        &self.pending_pages
+    }
+
     fn update(&mut self, page: usize, contracts_per_page: usize, id: u64) {
         if self.saved_pages.contains(&page) {
             // already saved
@@ -85,30 +103,178 @@ impl Store {
             contract_database,
             scrape_progress: Mutex::new(scrape_progress),
             path: scrape_progress_path,
+            pending_batch: Default::default(),
         })
     }
 
-    pub async fn already_exists(&self, id: u64, page: usize) -> bool {
-        let scrape_progress = self.scrape_progress.lock().unwrap();
+    pub async fn start_scrape_run(&self) -> anyhow::Result<i64> {
+        self.contract_database
+            .start_scrape_run()
+            .await
+            .context("Failed to record scrape start")
+    }
+
+    pub async fn finish_scrape_run(&self, id: i64, error: Option<&str>) -> anyhow::Result<()> {
+        self.contract_database
+            .finish_scrape_run(id, error)
+            .await
+            .context("Failed to record scrape end")
+    }
 
-        scrape_progress.saved_pages.contains(&page)
-            || scrape_progress
-                .pending_pages
-                .get(&page)
-                .map_or(false, |entry| entry.contains(&id))
+    pub async fn get_latest_publication_date(&self) -> anyhow::Result<Option<NaiveDate>> {
+        self.contract_database
+            .get_latest_publication_date()
+            .await
+            .context("Failed to get the latest publication date")
     }
 
-    pub async fn save_contract(&self, contract: Contract) -> anyhow::Result<()> {
+    pub async fn get_contract(&self, id: u64) -> anyhow::Result<Option<Contract>> {
         self.contract_database
-            .insert_contract(&contract)
+            .get_contract(id)
+            .await
+            .with_context(|| format!("Failed to load contract {id}"))
+    }
+
+    pub async fn list_contracts_to_refresh(
+        &self,
+        fetched_before: DateTime<Utc>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<u64>> {
+        self.contract_database
+            .list_contracts_to_refresh(fetched_before, limit)
+            .await
+            .context("Failed to list contracts to refresh")
+    }
+
+    pub async fn mark_contract_fetched(&self, id: u64) -> anyhow::Result<()> {
+        self.contract_database
+            .mark_contract_fetched(id)
+            .await
+            .with_context(|| format!("Failed to record that contract {id} was fetched"))
+    }
+
+    /// A snapshot of the pages scraped so far.
+    pub fn scrape_progress(&self) -> ScrapeProgress {
+        self.scrape_progress.lock().unwrap().clone()
+    }
+
+    /// Whether the contract was saved (ex: when polling for new contracts).
+    pub async fn is_saved(&self, id: u64) -> anyhow::Result<bool> {
+        let updated_at = self
+            .contract_database
+            .get_contract_updated_at(id)
+            .await
+            .with_context(|| format!("Failed to check if contract {id} is saved"))?;
+
+        Ok(updated_at.is_some())
+    }
+
+    /// Whether the contract at `page` was already scraped, or saved since (ex: when polling
+    /// for new contracts), in which case it is recorded in the progress as scraped.
+    pub async fn already_exists(
+        &self,
+        id: u64,
+        page: usize,
+        contracts_per_page: usize,
+    ) -> anyhow::Result<bool> {
+        {
+            let scrape_progress = self.scrape_progress.lock().unwrap();
+
+            let scraped = scrape_progress.saved_pages.contains(&page)
+                || scrape_progress
+                    .pending_pages
+                    .get(&page)
+                    .is_some_and(|entry| entry.contains(&id));
+            if scraped {
+                return Ok(true);
+            }
+        }
+
+        if !self.is_saved(id).await? {
+            return Ok(false);
+        }
+
+        self.record_scraped(id, page, contracts_per_page)?;
+        Ok(true)
+    }
+
+    /// Saves the contract, leaving its entities and the notification that it changed
+    /// to the next [Store::finish_batch].
+    pub async fn save_contract(&self, contract: Contract) -> anyhow::Result<()> {
+        let previous_entity_ids = self
+            .contract_database
+            .upsert_contract(&contract)
             .await
             .context("Failed to save contract in database")?;
 
+        {
+            let mut pending_batch = self.pending_batch.lock().unwrap();
+            pending_batch.contracts_saved = true;
+            // the entities removed from the contract are updated too
+            pending_batch.entity_ids.extend(
+                contract
+                    .contracting
+                    .iter()
+                    .chain(&contract.contracted)
+                    .map(|entity| entity.id)
+                    .chain(previous_entity_ids),
+            );
+        }
+
         self.search_database.save_contract(contract).await?;
 
         Ok(())
     }
 
+    /// Updates the entities of the contracts saved since the last batch in the search index,
+    /// and notifies that contracts changed (ex: to drop the cached search results).
+    /// It is done once per batch of contracts (ex: a page), since the entities are aggregated
+    /// across every contract of the entity.
+    pub async fn finish_batch(&self) -> anyhow::Result<()> {
+        let pending_batch = std::mem::take(&mut *self.pending_batch.lock().unwrap());
+        if !pending_batch.contracts_saved {
+            return Ok(());
+        }
+
+        // the contracts are saved, so this only delays dropping the cached search results
+        if let Err(e) = self.contract_database.notify_contracts_changed().await {
+            warn!("Failed to notify that contracts were saved: {e:?}");
+        }
+
+        let result = self.save_entities(&pending_batch.entity_ids).await;
+        if result.is_err() {
+            // left to the next batch
+            let mut pending = self.pending_batch.lock().unwrap();
+            pending.contracts_saved = true;
+            pending.entity_ids.extend(pending_batch.entity_ids);
+        }
+
+        result
+    }
+
+    async fn save_entities(&self, entity_ids: &HashSet<u64>) -> anyhow::Result<()> {
+        let entity_ids = entity_ids.iter().copied().collect::<Vec<_>>();
+        let entities = self
+            .contract_database
+            .get_searchable_entities(&entity_ids)
+            .await
+            .context("Failed to load entities from database")?;
+
+        self.search_database.save_entities(&entities).await?;
+
+        // the entities that were removed from their last contract aren't returned
+        let removed_ids = entity_ids
+            .iter()
+            .filter(|&&id| !entities.iter().any(|entity| entity.id == id))
+            .copied()
+            .collect::<Vec<_>>();
+        if !removed_ids.is_empty() {
+            self.search_database.delete_entities(&removed_ids).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn save_scraped_contract(
         &self,
         contract: Contract,
@@ -118,6 +284,15 @@ impl Store {
         let id = contract.id;
         self.save_contract(contract).await?;
 
+        self.record_scraped(id, page, contracts_per_page)
+    }
+
+    fn record_scraped(
+        &self,
+        id: u64,
+        page: usize,
+        contracts_per_page: usize,
+    ) -> anyhow::Result<()> {
         let mut scrape_progress = self.scrape_progress.lock().unwrap();
         scrape_progress.update(page, contracts_per_page, id);
 
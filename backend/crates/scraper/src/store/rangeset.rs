@@ -21,6 +21,11 @@ where
         RangeSet::<T> { ranges: Vec::new() }
     }
 
+    /// The disjoint ranges of the set, sorted and inclusive on both ends.
+    pub fn ranges(&self) -> &[(T, T)] {
+        &self.ranges
+    }
+
     pub fn insert(&mut self, value: T) {
         if self.ranges.is_empty() {
             self.ranges.push((value, value));
@@ -0,0 +1,8 @@
+-- The search settings updated by the admin, shared by every backend
+CREATE TABLE search_settings (
+    -- there is a single row
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    version INTEGER NOT NULL,
+    settings JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,13 @@
+CREATE TABLE api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    -- who the key was issued to
+    name TEXT NOT NULL,
+    -- only the SHA-256 of the key is stored, the key is shown once when issued
+    key_hash TEXT NOT NULL UNIQUE,
+    tier TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    request_count BIGINT NOT NULL DEFAULT 0,
+    rate_limited_count BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,14 @@
+-- the rate limits shared by the api replicas, unlogged since losing them on a crash is harmless
+CREATE UNLOGGED TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    -- the theoretical arrival time of the next request, in microseconds since the unix epoch
+    tat BIGINT NOT NULL
+);
+
+CREATE UNLOGGED TABLE rate_limit_penalties (
+    key TEXT PRIMARY KEY,
+    violations INTEGER NOT NULL,
+    until TIMESTAMPTZ NOT NULL,
+    -- when the violations are forgotten
+    expires_at TIMESTAMPTZ NOT NULL
+);
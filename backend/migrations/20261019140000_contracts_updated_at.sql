@@ -0,0 +1,2 @@
+-- Used as the Last-Modified of the contracts, so that conditional requests don't load them
+ALTER TABLE contracts ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
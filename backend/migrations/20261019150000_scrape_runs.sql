@@ -0,0 +1,11 @@
+-- Every run of the scraper, to know when it last succeeded
+CREATE TABLE scrape_runs (
+    id BIGSERIAL PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- null while running, or if the scraper stopped before finishing it
+    finished_at TIMESTAMPTZ,
+    -- null if it succeeded
+    error TEXT
+);
+
+CREATE INDEX scrape_runs_finished_at_idx ON scrape_runs (finished_at DESC) WHERE error IS NULL;
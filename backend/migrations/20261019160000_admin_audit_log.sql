@@ -0,0 +1,13 @@
+-- Every request to the admin routes, including the rejected ones
+CREATE TABLE admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- the method and route of the action (ex: "POST /api/admin/scrape")
+    action TEXT NOT NULL,
+    -- the requested path, which has the parameters of the route (ex: the id of the contract)
+    path TEXT NOT NULL,
+    status SMALLINT NOT NULL,
+    client_ip TEXT
+);
+
+CREATE INDEX admin_audit_log_created_at_idx ON admin_audit_log (created_at DESC);
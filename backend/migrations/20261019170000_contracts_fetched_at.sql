@@ -0,0 +1,5 @@
+-- When the details of the contracts were last fetched from Portal BASE, even if they didn't change,
+-- so that the open contracts are fetched again to get the fields filled in when they are closed
+ALTER TABLE contracts ADD COLUMN fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX contracts_open_fetched_at_idx ON contracts (fetched_at) WHERE close_date IS NULL;
@@ -5,12 +5,13 @@ import {
   type SearchContractsRequest,
   type SearchContractsResponse,
   type Statistics,
+  type ValueFilters,
 } from "$lib/types/api";
 import { validateEnumOrDefault } from "./utils";
 
 export type ApiResult<T> =
   | { ok: true; data: T }
-  | { ok: false; status: number; message: string };
+  | { ok: false; status: number; code?: string; message: string };
 
 async function apiFetch<T>(
   fetchFn: typeof fetch,
@@ -28,6 +29,7 @@ async function apiFetch<T>(
       return {
         ok: false,
         status: response.status,
+        code: errorData.code,
         message: errorData.message || `Error ${response.status}: ${response.statusText}`,
       };
     }
@@ -47,12 +49,28 @@ export const DEFAULT_SEARCH_REQUEST = {
   page: 1,
 } as const;
 
+// The search made by the page, where the sort is only set if it was chosen,
+// so that the backend sorts by relevance when there is a query
+export interface SearchPageRequest {
+  query: string;
+  sort?: Sort.SortBy;
+  filters: Filters;
+  page: number;
+}
+
+// The sort used by the backend when none is sent
+export function defaultSort(query: string): Sort.SortBy {
+  return query.trim()
+    ? { field: "relevance", direction: "descending" }
+    : DEFAULT_SEARCH_REQUEST.sort;
+}
+
 export function searchContracts(
   data: SearchContractsRequest,
   fetchFn = fetch,
   signal?: AbortSignal,
 ): Promise<ApiResult<SearchContractsResponse>> {
-  return apiFetch(fetchFn, `/api/search`, {
+  return apiFetch(fetchFn, `/api/v1/search`, {
     method: "POST",
     body: JSON.stringify(data),
     signal,
@@ -60,28 +78,29 @@ export function searchContracts(
 }
 
 export function getContract(id: number, fetchFn = fetch): Promise<ApiResult<GetContractResponse>> {
-  return apiFetch(fetchFn, `/api/contract/${id}`);
+  return apiFetch(fetchFn, `/api/v1/contract/${id}`);
 }
 
-export function parseSearchRequestFromParams(
-  params: URLSearchParams,
-): Required<SearchContractsRequest> {
+export function parseSearchRequestFromParams(params: URLSearchParams): SearchPageRequest {
   const query = params.get("query") || DEFAULT_SEARCH_REQUEST.query;
+  const fallbackSort = defaultSort(query);
   const sortField = validateEnumOrDefault(
     params.get("sortField"),
     Sort.fields,
-    DEFAULT_SEARCH_REQUEST.sort!.field,
+    fallbackSort.field,
   );
   const sortDirection = validateEnumOrDefault(
     params.get("sortDirection"),
     Sort.directions,
-    DEFAULT_SEARCH_REQUEST.sort!.direction,
+    fallbackSort.direction,
   );
   const pageParam = params.get("page");
   let page = pageParam ? parseInt(pageParam, 10) : DEFAULT_SEARCH_REQUEST.page!;
   page = Math.max(1, page);
 
-  const sort: Sort.SortBy = { field: sortField, direction: sortDirection };
+  const sort: Sort.SortBy | undefined = params.has("sortField")
+    ? { field: sortField, direction: sortDirection }
+    : undefined;
 
   let filters: Filters = {};
 
@@ -92,10 +111,17 @@ export function parseSearchRequestFromParams(
     }
   };
 
+  const addValuesParam = (key: keyof ValueFilters) => {
+    const values = params.getAll(key).filter((value) => value);
+    if (values.length > 0) {
+      filters[key] = values.length === 1 ? values[0] : values;
+    }
+  };
+
   addParam("minId", (v) => parseInt(v, 10));
   addParam("maxId", (v) => parseInt(v, 10));
-  addParam("contracted");
-  addParam("contracting");
+  addValuesParam("contracted");
+  addValuesParam("contracting");
   addParam("startPublicationDate");
   addParam("endPublicationDate");
   addParam("startSigningDate");
@@ -106,16 +132,12 @@ export function parseSearchRequestFromParams(
   return { query, sort, filters, page };
 }
 
-export function buildSearchParams(request: Required<SearchContractsRequest>): URLSearchParams {
+export function buildSearchParams(request: SearchPageRequest): URLSearchParams {
   const params = new URLSearchParams();
 
   if (request.query) params.set("query", request.query);
 
-  const defaultSort = DEFAULT_SEARCH_REQUEST.sort;
-  if (
-    request.sort.field !== defaultSort.field ||
-    request.sort.direction !== defaultSort.direction
-  ) {
+  if (request.sort) {
     params.set("sortField", request.sort.field);
     params.set("sortDirection", request.sort.direction);
   }
@@ -125,7 +147,9 @@ export function buildSearchParams(request: Required<SearchContractsRequest>): UR
   }
 
   Object.entries(request.filters).forEach(([field, value]) => {
-    if (value != null && value !== "") {
+    if (Array.isArray(value)) {
+      value.forEach((item) => params.append(field, item));
+    } else if (value != null && value !== "" && typeof value !== "object") {
       params.set(field, `${value}`);
     }
   });
@@ -134,7 +158,7 @@ export function buildSearchParams(request: Required<SearchContractsRequest>): UR
 }
 
 export function fetchStatistics(fetchFn = fetch): Promise<ApiResult<Statistics>> {
-  return apiFetch(fetchFn, `/api/statistics`);
+  return apiFetch(fetchFn, `/api/v1/statistics`);
 }
 
 export function getBaseGovContractUrl(contractId: number) {
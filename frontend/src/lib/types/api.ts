@@ -52,6 +52,11 @@ export interface SearchContractsRequest {
   sort?: Sort.SortBy;
   filters?: Filters;
   page?: number;
+  hitsPerPage?: number;
+  offset?: number;
+  limit?: number;
+  facets?: boolean;
+  semanticRatio?: number;
 }
 
 export interface SearchContractsResponse {
@@ -61,9 +66,73 @@ export interface SearchContractsResponse {
   totalPages: number;
   elapsedMillis: number;
   hitsPerPage: number;
+  offset?: number;
+  facets?: Facets;
+  parsedQuery?: ParsedQuery;
 }
 
-export type GetContractResponse = Contract | null;
+export interface ParsedQuery {
+  text: string;
+  attributesToSearchOn?: string[];
+  tokens: QueryToken[];
+}
+
+export type QueryToken =
+  | { type: "term"; value: string }
+  | { type: "phrase"; value: string }
+  | { type: "negation"; value: string }
+  | { type: "operator"; operator: string; value: string; negated: boolean };
+
+export interface Facets {
+  contractingProcedureType: FacetValue[];
+  contractTypes: FacetValue[];
+  publicationYear: FacetValue[];
+  cpvDivision: FacetValue[];
+  district: FacetValue[];
+  contracting: FacetValue[];
+  contracted: FacetValue[];
+  price: PriceRange | null;
+}
+
+export interface FacetValue {
+  value: string;
+  count: number;
+}
+
+export interface PriceRange {
+  min: number;
+  max: number;
+}
+
+export type GetContractResponse = Contract;
+
+// The fields of a contract returned in the lists of contracts
+export interface ContractSummary {
+  id: number;
+  contractingProcedureType: string;
+  publicationDate: string;
+  signingDate: string | null;
+  ccp: boolean;
+  objectBriefDescription: string;
+  description: string | null;
+  initialContractualPrice: number;
+  contracting: Entity[];
+  contracted: Entity[];
+  cpvs: Cpv[];
+  regime: string | null;
+  contractTypes: string;
+  executionPlaces: string[];
+  contractFundamentationType: string;
+  contestants: Entity[];
+  invitees: Entity[];
+  documents: Document[];
+  contractingProcedureUrl: string | null;
+  announcementId: number | null;
+  closeDate: string | null;
+  totalEffectivePrice: number | null;
+}
+
+export type GetSimilarContractsResponse = ContractSummary[];
 
 export namespace Sort {
   export interface SortBy {
@@ -71,24 +140,58 @@ export namespace Sort {
     direction: Direction;
   }
 
-  export const fields = ["id", "publicationDate", "signingDate", "price"] as const;
+  export const fields = [
+    "relevance",
+    "id",
+    "publicationDate",
+    "signingDate",
+    "price",
+    "effectivePrice",
+    "closeDate",
+    "contestants",
+  ] as const;
   export type Field = (typeof fields)[number];
 
   export const directions = ["ascending", "descending"] as const;
   export type Direction = (typeof directions)[number];
 }
 
-export interface Filters {
+export interface Filters extends ValueFilters {
   minId?: number;
   maxId?: number;
   startPublicationDate?: string;
   endPublicationDate?: string;
   startSigningDate?: string;
   endSigningDate?: string;
-  contracted?: string;
-  contracting?: string;
+  startCloseDate?: string;
+  endCloseDate?: string;
   minPrice?: number;
   maxPrice?: number;
+  minEffectivePrice?: number;
+  maxEffectivePrice?: number;
+  ccp?: boolean;
+  minContestants?: number;
+  maxContestants?: number;
+  hasDocuments?: boolean;
+  // Contracts matching any of these values are excluded from the results
+  exclude?: ValueFilters;
+}
+
+// Every value filter also accepts a list of values where any of them can match
+export type FilterValues = string | string[];
+
+export interface ValueFilters {
+  contracted?: FilterValues;
+  contracting?: FilterValues;
+  // Matches the NIF of either the contracting or the contracted entities
+  nif?: FilterValues;
+  contractingProcedureType?: FilterValues;
+  contractTypes?: FilterValues;
+  regime?: FilterValues;
+  // Matches either a full CPV code or a CPV prefix
+  cpv?: FilterValues;
+  // Matches either a full execution place or its district
+  executionPlace?: FilterValues;
 }
 
 export interface MatchingRanges {
@@ -111,3 +214,20 @@ export interface Statistics {
   totalSpentLast7Days: number;
   contractsLast7Days: number;
 }
+
+export interface EntitySuggestion {
+  id: number;
+  nif: string;
+  name: string;
+  aliases: string[];
+  contractingCount: number;
+  contractedCount: number;
+  contractCount: number;
+  totalValue: number;
+}
+
+export interface ApiError {
+  code: string;
+  message: string;
+  requestId?: string;
+}
@@ -11,6 +11,10 @@ export const load: PageLoad = async ({ params, fetch }) => {
   const result = await getContract(contractId, fetch);
 
   if (!result.ok) {
+    if (result.code === "contract_not_found") {
+      return { contract: undefined, rateLimited: false, error: null };
+    }
+
     const rateLimited = result.status === 429;
     return {
       contract: undefined,
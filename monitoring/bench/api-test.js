@@ -58,7 +58,7 @@ const sortOptions = [
 ];
 
 export default function () {
-  const baseUrl = "http://localhost/api/search";
+  const baseUrl = "http://localhost/api/v1/search";
 
   const query =
     Math.random() < 0.3